@@ -0,0 +1,78 @@
+//! Standard base64 encoding, as used by http headers and PEM files.
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes `input` with padding.
+pub(crate) fn encode(input: &[u8]) -> String {
+    let mut encoded = String::with_capacity(input.chunks(3).len() * 4);
+    for chunk in input.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decodes `input`, ignoring whitespace. Returns `None` if `input`
+/// is not valid base64.
+pub(crate) fn decode(input: &str) -> Option<Vec<u8>> {
+    let input: Vec<u8> = input
+        .bytes()
+        .filter(|byte| !byte.is_ascii_whitespace())
+        .collect();
+    let chunks = input.chunks_exact(4);
+    if !chunks.remainder().is_empty() {
+        return None;
+    }
+    let mut decoded = Vec::with_capacity(input.len() / 4 * 3);
+    for (index, chunk) in chunks.enumerate() {
+        let is_last = index == input.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|&&byte| byte == b'=').count();
+        if padding > 2 || (padding > 0 && !is_last) {
+            return None;
+        }
+        let mut n = 0u32;
+        for &byte in &chunk[..4 - padding] {
+            let value = ALPHABET.iter().position(|&c| c == byte)?;
+            n = (n << 6) | value as u32;
+        }
+        n <<= 6 * padding as u32;
+        decoded.extend_from_slice(&n.to_be_bytes()[1..4 - padding]);
+    }
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        assert_eq!("", encode(b""));
+        assert_eq!("Zg==", encode(b"f"));
+        assert_eq!("Zm8=", encode(b"fo"));
+        assert_eq!("Zm9v", encode(b"foo"));
+        assert_eq!("dXNlcjpwYXNz", encode(b"user:pass"));
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(Some(b"".to_vec()), decode(""));
+        assert_eq!(Some(b"f".to_vec()), decode("Zg=="));
+        assert_eq!(Some(b"fo".to_vec()), decode("Zm8="));
+        assert_eq!(Some(b"user:pass".to_vec()), decode("dXNl\ncjpw\r\nYXNz"));
+        assert_eq!(None, decode("Zg="));
+        assert_eq!(None, decode("Zg==Zm8="));
+        assert_eq!(None, decode("Z!=="));
+    }
+}
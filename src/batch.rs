@@ -0,0 +1,157 @@
+use crate::transport::Transport;
+use crate::{Error, StellarToml, StellarTomlClient};
+use futures_core::Stream;
+use futures_util::stream::{self, StreamExt};
+use std::time::{Duration, Instant};
+
+/// The result of resolving one domain of a batch.
+#[derive(Debug, Clone)]
+pub struct Resolution {
+    /// The resolved domain.
+    pub domain: String,
+    /// The parsed `stellar.toml` file, or the error that occurred.
+    pub result: Result<StellarToml, Error>,
+    /// How long it took to resolve the domain.
+    pub elapsed: Duration,
+}
+
+/// The results of resolving a batch of domains.
+#[derive(Debug, Clone)]
+pub struct BatchSummary {
+    /// The result of each domain, in completion order.
+    pub resolutions: Vec<Resolution>,
+    /// How long it took to resolve the whole batch.
+    pub elapsed: Duration,
+}
+
+impl BatchSummary {
+    /// Returns the domains that were resolved successfully.
+    pub fn successes(&self) -> impl Iterator<Item = &Resolution> {
+        self.resolutions.iter().filter(|r| r.result.is_ok())
+    }
+
+    /// Returns the domains that failed to resolve.
+    pub fn failures(&self) -> impl Iterator<Item = &Resolution> {
+        self.resolutions.iter().filter(|r| r.result.is_err())
+    }
+}
+
+impl<T> StellarTomlClient<T>
+where
+    T: Transport,
+{
+    /// Resolves the `stellar.toml` files of `domains`, with at most
+    /// `concurrency` lookups in flight at the same time.
+    ///
+    /// Returns a stream that yields the result of each domain as soon
+    /// as it completes, so results are not in the same order as
+    /// `domains`.
+    ///
+    #[cfg_attr(feature = "hyper-transport", doc = "```rust")]
+    #[cfg_attr(not(feature = "hyper-transport"), doc = "```ignore")]
+    /// use futures_util::stream::StreamExt;
+    /// use stellar_toml::StellarTomlClient;
+    ///
+    /// # async fn run() {
+    /// let client = StellarTomlClient::new();
+    /// let mut resolutions = client.resolve_many(vec!["www.stellar.org", "example.org"], 10);
+    /// while let Some(resolution) = resolutions.next().await {
+    ///     println!("{}: {:?}", resolution.domain, resolution.elapsed);
+    /// }
+    /// # }
+    /// ```
+    pub fn resolve_many<I, S>(
+        &self,
+        domains: I,
+        concurrency: usize,
+    ) -> impl Stream<Item = Resolution> + Send + 'static
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let client = self.clone();
+        let domains: Vec<String> = domains.into_iter().map(Into::into).collect();
+        stream::iter(domains)
+            .map(move |domain| {
+                let client = client.clone();
+                async move {
+                    let start = Instant::now();
+                    let result = client.resolve(&domain).await;
+                    Resolution {
+                        domain,
+                        result,
+                        elapsed: start.elapsed(),
+                    }
+                }
+            })
+            .buffer_unordered(concurrency.max(1))
+    }
+
+    /// Resolves the `stellar.toml` files of `domains`, with at most
+    /// `concurrency` lookups in flight at the same time, and returns
+    /// all the results once they complete.
+    pub async fn resolve_all<I, S>(&self, domains: I, concurrency: usize) -> BatchSummary
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let start = Instant::now();
+        let resolutions = self.resolve_many(domains, concurrency).collect().await;
+        BatchSummary {
+            resolutions,
+            elapsed: start.elapsed(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{MemoryResponse, MemoryTransport, ResponseFuture};
+    use http::Request;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[derive(Debug, Clone, Default)]
+    struct CountingTransport {
+        inner: MemoryTransport,
+        active: Arc<AtomicUsize>,
+        max_active: Arc<AtomicUsize>,
+    }
+
+    impl Transport for CountingTransport {
+        fn send(&self, request: Request<()>) -> ResponseFuture {
+            let response = self.inner.send(request);
+            let active = self.active.clone();
+            let max_active = self.max_active.clone();
+            Box::pin(async move {
+                let current = active.fetch_add(1, Ordering::SeqCst) + 1;
+                max_active.fetch_max(current, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                active.fetch_sub(1, Ordering::SeqCst);
+                response.await
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_all_bounds_concurrency() {
+        let transport = CountingTransport::default();
+        let domains: Vec<String> = (0..20)
+            .map(|i| format!("anchor{}.example.org", i))
+            .collect();
+        for domain in domains.iter().skip(1) {
+            let url = format!("https://{}/.well-known/stellar.toml", domain);
+            transport
+                .inner
+                .insert(&url, MemoryResponse::ok("VERSION = \"2.0.0\""));
+        }
+        let client = StellarTomlClient::with_transport(transport.clone());
+        let summary = client.resolve_all(domains, 4).await;
+        assert_eq!(20, summary.resolutions.len());
+        assert_eq!(19, summary.successes().count());
+        let failures: Vec<_> = summary.failures().map(|r| r.domain.as_str()).collect();
+        assert_eq!(vec!["anchor0.example.org"], failures);
+        assert_eq!(4, transport.max_active.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,240 @@
+//! Blocking API to resolve `stellar.toml` files.
+//!
+//! The blocking client wraps the async `StellarTomlClient` and runs
+//! its lookups on a runtime owned by the client, so it can be used
+//! from synchronous code without setting up a runtime.
+//!
+//! ```rust
+//! # fn run() -> std::result::Result<(), stellar_toml::Error> {
+//! let stellar = stellar_toml::blocking::resolve("www.stellar.org")?;
+//! println!("{:?}", stellar.network_passphrase);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The blocking client must not be used from async code, calling its
+//! methods from a task running on a runtime panics.
+use crate::transport::{HyperTransport, Transport};
+use crate::{BatchSummary, ClientBuilder, Error, FetchedStellarToml, StellarToml};
+use http::uri::Uri;
+use std::sync::Arc;
+use tokio::runtime::{Builder, Runtime};
+
+/// A blocking client used to resolve `stellar.toml` files.
+///
+/// The client is configured with the same `ClientBuilder` as the
+/// async client. Cloning the client is cheap and all clones share the
+/// same connection pool, cache and runtime.
+///
+/// ```rust
+/// use std::time::Duration;
+/// use stellar_toml::blocking::StellarTomlClient;
+///
+/// # fn run() -> std::result::Result<(), stellar_toml::Error> {
+/// let client = StellarTomlClient::builder()
+///     .timeout(Duration::from_secs(30))
+///     .build_blocking();
+/// let stellar = client.resolve("www.stellar.org")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct StellarTomlClient<T = HyperTransport> {
+    inner: crate::StellarTomlClient<T>,
+    runtime: Arc<Runtime>,
+}
+
+impl StellarTomlClient<HyperTransport> {
+    /// Creates a new client with the default configuration.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the runtime of the client cannot be created.
+    pub fn new() -> StellarTomlClient<HyperTransport> {
+        ClientBuilder::new().build_blocking()
+    }
+
+    /// Returns a builder to configure a new client.
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::new()
+    }
+}
+
+impl Default for StellarTomlClient<HyperTransport> {
+    fn default() -> StellarTomlClient<HyperTransport> {
+        StellarTomlClient::new()
+    }
+}
+
+impl<T> Clone for StellarTomlClient<T> {
+    fn clone(&self) -> StellarTomlClient<T> {
+        StellarTomlClient {
+            inner: self.inner.clone(),
+            runtime: self.runtime.clone(),
+        }
+    }
+}
+
+impl<T> StellarTomlClient<T>
+where
+    T: Transport,
+{
+    /// Creates a new client that sends requests using `transport`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the runtime of the client cannot be created.
+    pub fn with_transport(transport: T) -> StellarTomlClient<T> {
+        ClientBuilder::new().build_blocking_with_transport(transport)
+    }
+
+    /// Returns a reference to the client transport.
+    pub fn transport(&self) -> &T {
+        self.inner.transport()
+    }
+
+    /// Returns a parsed `stellar.toml` file at `domain`.
+    ///
+    /// This method will always fetch the `stellar.toml` file using https.
+    /// See `resolve` for more information.
+    pub fn resolve(&self, domain: &str) -> Result<StellarToml, Error> {
+        self.runtime.block_on(self.inner.resolve(domain))
+    }
+
+    /// Returns a parsed `stellar.toml` file at `url`.
+    pub fn resolve_url(&self, url: &Uri) -> Result<StellarToml, Error> {
+        self.runtime.block_on(self.inner.resolve_url(url))
+    }
+
+    /// Returns a parsed `stellar.toml` file at `domain`, together with
+    /// information about how it was fetched.
+    pub fn resolve_with_metadata(&self, domain: &str) -> Result<FetchedStellarToml, Error> {
+        self.runtime
+            .block_on(self.inner.resolve_with_metadata(domain))
+    }
+
+    /// Returns a parsed `stellar.toml` file at `url`, together with
+    /// information about how it was fetched.
+    pub fn resolve_url_with_metadata(&self, url: &Uri) -> Result<FetchedStellarToml, Error> {
+        self.runtime
+            .block_on(self.inner.resolve_url_with_metadata(url))
+    }
+
+    /// Resolves the `stellar.toml` files of `domains`, with at most
+    /// `concurrency` lookups in flight at the same time, and returns
+    /// all the results once they complete.
+    pub fn resolve_all<I, S>(&self, domains: I, concurrency: usize) -> BatchSummary
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.runtime
+            .block_on(self.inner.resolve_all(domains, concurrency))
+    }
+}
+
+impl ClientBuilder {
+    /// Builds a blocking client that uses a `HyperTransport`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the runtime of the client cannot be created.
+    pub fn build_blocking(self) -> StellarTomlClient<HyperTransport> {
+        StellarTomlClient {
+            inner: self.build(),
+            runtime: Arc::new(new_runtime()),
+        }
+    }
+
+    /// Builds a blocking client that sends requests using `transport`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the runtime of the client cannot be created.
+    pub fn build_blocking_with_transport<T: Transport>(self, transport: T) -> StellarTomlClient<T> {
+        StellarTomlClient {
+            inner: self.build_with_transport(transport),
+            runtime: Arc::new(new_runtime()),
+        }
+    }
+}
+
+/// Returns a parsed `stellar.toml` file at `domain`.
+///
+/// This function will always fetch the `stellar.toml` file using https.
+/// See `stellar_toml::resolve` for more information.
+///
+/// This function uses a new default `StellarTomlClient` on every
+/// call, use a shared client to reuse connections between lookups.
+pub fn resolve(domain: &str) -> Result<StellarToml, Error> {
+    StellarTomlClient::new().resolve(domain)
+}
+
+/// Returns a parsed `stellar.toml` file at `url`.
+///
+/// This function uses a new default `StellarTomlClient` on every
+/// call, use a shared client to reuse connections between lookups.
+pub fn resolve_url(url: &Uri) -> Result<StellarToml, Error> {
+    StellarTomlClient::new().resolve_url(url)
+}
+
+fn new_runtime() -> Runtime {
+    Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("failed to create the tokio runtime")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{MemoryResponse, MemoryTransport};
+    use std::thread;
+
+    const URL: &str = "https://example.org/.well-known/stellar.toml";
+
+    #[test]
+    fn test_blocking_resolve() {
+        let transport =
+            MemoryTransport::new().with_response(URL, MemoryResponse::ok("VERSION=\"2.0.0\"\n"));
+        let client = StellarTomlClient::with_transport(transport);
+        let stellar = client.resolve("example.org").unwrap();
+        assert_eq!(Some("2.0.0".to_string()), stellar.version);
+        assert!(matches!(
+            client.resolve("missing.example.org"),
+            Err(Error::ClientError(_))
+        ));
+
+        let summary = client.resolve_all(vec!["example.org", "missing.example.org"], 2);
+        assert_eq!(1, summary.successes().count());
+        assert_eq!(1, summary.failures().count());
+    }
+
+    #[test]
+    fn test_blocking_resolve_from_threads() {
+        let transport =
+            MemoryTransport::new().with_response(URL, MemoryResponse::ok("VERSION=\"2.0.0\"\n"));
+        let client = StellarTomlClient::builder().build_blocking_with_transport(transport);
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let client = client.clone();
+                thread::spawn(move || client.resolve("example.org").unwrap())
+            })
+            .collect();
+        for thread in threads {
+            assert_eq!(Some("2.0.0".to_string()), thread.join().unwrap().version);
+        }
+    }
+
+    #[test]
+    fn test_blocking_resolve_with_hyper_transport() {
+        let client = StellarTomlClient::new();
+        let url = "http://127.0.0.1:1/.well-known/stellar.toml"
+            .parse()
+            .unwrap();
+        assert!(matches!(
+            client.resolve_url(&url),
+            Err(Error::BlockedAddress { .. })
+        ));
+    }
+}
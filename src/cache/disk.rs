@@ -0,0 +1,225 @@
+use crate::PeerCertificate;
+use bytes::Bytes;
+use futures_util::future;
+use http::uri::Uri;
+use std::fs;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::task::{Poll, Waker};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Cache of raw `stellar.toml` files persisted to a directory.
+///
+/// Each entry is stored in two files named after the hash of the
+/// url: a `.toml` file with the raw response body and a `.meta`
+/// file with information about the fetch.
+///
+/// The files are read and written without blocking the executor, see
+/// `unblock`. Failures to update the cache are reported as `tracing`
+/// events and otherwise ignored, since the entry is still cached in
+/// memory.
+#[derive(Debug)]
+pub(crate) struct DiskCache {
+    directory: Arc<PathBuf>,
+}
+
+/// An entry of the disk cache.
+#[derive(Debug)]
+pub(crate) struct DiskEntry {
+    pub(crate) body: Bytes,
+    pub(crate) metadata: Metadata,
+}
+
+/// Information about how an entry was fetched.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Metadata {
+    /// The requested url.
+    pub(crate) url: String,
+    /// The url the file was fetched from, after following redirects.
+    pub(crate) final_url: String,
+    /// The urls that responded with a redirect.
+    pub(crate) redirects: Vec<String>,
+    /// The status code of the response.
+    pub(crate) status: u16,
+    /// The headers of the response, without the values that are not
+    /// valid strings.
+    pub(crate) headers: Vec<(String, String)>,
+    /// How long it took to fetch the entry, in milliseconds.
+    pub(crate) elapsed_ms: u64,
+    /// When the entry was fetched, in seconds since the unix epoch.
+    pub(crate) fetched_at: u64,
+    /// When the entry expires, in seconds since the unix epoch.
+    pub(crate) expires_at: u64,
+    pub(crate) etag: Option<String>,
+    pub(crate) last_modified: Option<String>,
+    /// The certificate chain of the server of `final_url`. Must be
+    /// the last field, because toml tables follow values.
+    pub(crate) peer_certificates: Vec<PeerCertificate>,
+}
+
+impl DiskCache {
+    pub(crate) fn new(directory: PathBuf) -> DiskCache {
+        DiskCache {
+            directory: Arc::new(directory),
+        }
+    }
+
+    /// Returns the entry for `url`, or `None` if it's missing or
+    /// can't be read.
+    pub(crate) async fn load(&self, url: &Uri) -> Option<DiskEntry> {
+        let (body_path, metadata_path) = self.paths(url);
+        let url = url.to_string();
+        unblock(move || {
+            let metadata = fs::read(metadata_path).ok()?;
+            let metadata: Metadata = toml::from_slice(&metadata).ok()?;
+            if metadata.url != url {
+                return None;
+            }
+            let body = fs::read(body_path).ok()?;
+            Some(DiskEntry {
+                body: body.into(),
+                metadata,
+            })
+        })
+        .await
+    }
+
+    /// Stores `entry` for `url`, replacing any existing entry.
+    pub(crate) async fn store(&self, url: &Uri, entry: DiskEntry) {
+        let directory = self.directory.clone();
+        let (body_path, metadata_path) = self.paths(url);
+        let result = unblock(move || {
+            fs::create_dir_all(&*directory)?;
+            let metadata = toml::to_vec(&entry.metadata)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            write_atomic(&body_path, &entry.body)?;
+            write_atomic(&metadata_path, &metadata)
+        })
+        .await;
+        report_error(url, result);
+    }
+
+    /// Updates the metadata of the entry for `url`.
+    pub(crate) async fn store_metadata(&self, url: &Uri, metadata: Metadata) {
+        let (_, metadata_path) = self.paths(url);
+        let result = unblock(move || {
+            let metadata = toml::to_vec(&metadata)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            write_atomic(&metadata_path, &metadata)
+        })
+        .await;
+        report_error(url, result);
+    }
+
+    /// Removes the entry for `url`, if any.
+    pub(crate) async fn remove(&self, url: &Uri) {
+        let (body_path, metadata_path) = self.paths(url);
+        let result = unblock(move || {
+            for path in &[metadata_path, body_path] {
+                match fs::remove_file(path) {
+                    Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                    _ => {}
+                }
+            }
+            Ok(())
+        })
+        .await;
+        report_error(url, result);
+    }
+
+    fn paths(&self, url: &Uri) -> (PathBuf, PathBuf) {
+        let name = format!("{:016x}", fnv1a(url.to_string().as_bytes()));
+        (
+            self.directory.join(format!("{}.toml", name)),
+            self.directory.join(format!("{}.meta", name)),
+        )
+    }
+}
+
+/// Reports a failure to update the entry for `url`.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+fn report_error(url: &Uri, result: io::Result<()>) {
+    if let Err(err) = result {
+        trace_event!(warn, url = %url, error = %err, "disk cache update failed");
+    }
+}
+
+/// Runs `f`, which blocks on file system operations, without blocking
+/// the executor: on the `tokio` blocking thread pool within a `tokio`
+/// runtime, or on a new thread otherwise.
+async fn unblock<F, T>(f: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    #[cfg(feature = "tokio")]
+    {
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            return match runtime.spawn_blocking(f).await {
+                Ok(result) => result,
+                Err(err) => panic::resume_unwind(err.into_panic()),
+            };
+        }
+    }
+    type State<T> = (Option<thread::Result<T>>, Option<Waker>);
+    let state: Arc<Mutex<State<T>>> = Arc::new(Mutex::new((None, None)));
+    let thread_state = state.clone();
+    thread::spawn(move || {
+        let result = panic::catch_unwind(AssertUnwindSafe(f));
+        let mut state = thread_state.lock().unwrap();
+        state.0 = Some(result);
+        if let Some(waker) = state.1.take() {
+            waker.wake();
+        }
+    });
+    let result = future::poll_fn(move |cx| {
+        let mut state = state.lock().unwrap();
+        match state.0.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.1 = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    })
+    .await;
+    result.unwrap_or_else(|err| panic::resume_unwind(err))
+}
+
+/// Converts `time` to seconds since the unix epoch.
+pub(crate) fn to_unix(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+/// Converts seconds since the unix epoch to `SystemTime`, or `None`
+/// if it cannot be represented.
+pub(crate) fn from_unix(secs: u64) -> Option<SystemTime> {
+    UNIX_EPOCH.checked_add(Duration::from_secs(secs))
+}
+
+/// Writes `contents` to a temporary file, then moves it to `path` so
+/// that readers never observe a partially written file.
+fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let tmp = path.with_extension(format!("tmp{}", fastrand::u32(..)));
+    fs::write(&tmp, contents)?;
+    let result = fs::rename(&tmp, path);
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// 64 bit FNV-1a hash, used because it's stable across releases.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
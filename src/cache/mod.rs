@@ -0,0 +1,484 @@
+use crate::{compliance, FetchedStellarToml, StellarToml};
+use disk::{from_unix, to_unix, DiskCache, DiskEntry, Metadata};
+use http::header::{HeaderName, HeaderValue, CACHE_CONTROL, ETAG, LAST_MODIFIED};
+use http::uri::Uri;
+use http::{HeaderMap, StatusCode};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+mod disk;
+
+/// Longest time an entry is cached for, whatever its `max-age`.
+const MAX_TTL: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// Configuration of the in-memory cache of resolved `stellar.toml` files.
+///
+/// Entries are cached for the duration specified by the
+/// `Cache-Control: max-age` header of the response, or for the
+/// default ttl if the header is missing. Responses with
+/// `Cache-Control: no-store` are never cached. Expired entries are
+/// revalidated using their `ETag` and `Last-Modified` headers, if
+/// present.
+///
+/// If a cache directory is set, the raw `stellar.toml` files and
+/// information about how they were fetched are also persisted to
+/// disk, so that they survive restarts and can be served in offline
+/// mode.
+///
+#[cfg_attr(feature = "hyper-transport", doc = "```rust")]
+#[cfg_attr(not(feature = "hyper-transport"), doc = "```ignore")]
+/// use std::time::Duration;
+/// use stellar_toml::{CacheConfig, StellarTomlClient};
+///
+/// let client = StellarTomlClient::builder()
+///     .cache(CacheConfig::new().default_ttl(Duration::from_secs(600)))
+///     .build();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheConfig {
+    default_ttl: Duration,
+    capacity: usize,
+    directory: Option<PathBuf>,
+}
+
+impl CacheConfig {
+    /// Creates a new cache configuration, with a default ttl of 5
+    /// minutes and a capacity of 1000 entries.
+    pub fn new() -> CacheConfig {
+        CacheConfig {
+            default_ttl: Duration::from_secs(300),
+            capacity: 1000,
+            directory: None,
+        }
+    }
+
+    /// Persists cached entries to `directory`, creating it if needed.
+    ///
+    /// The capacity only limits the entries kept in memory, entries
+    /// persisted to disk are never evicted.
+    pub fn directory<P: Into<PathBuf>>(mut self, directory: P) -> CacheConfig {
+        self.directory = Some(directory.into());
+        self
+    }
+
+    /// Sets the ttl of responses without a `Cache-Control: max-age` header.
+    pub fn default_ttl(mut self, ttl: Duration) -> CacheConfig {
+        self.default_ttl = ttl;
+        self
+    }
+
+    /// Sets the maximum number of cached entries.
+    pub fn capacity(mut self, capacity: usize) -> CacheConfig {
+        self.capacity = capacity;
+        self
+    }
+}
+
+impl Default for CacheConfig {
+    fn default() -> CacheConfig {
+        CacheConfig::new()
+    }
+}
+
+/// Headers used to revalidate an expired entry.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Validators {
+    pub(crate) etag: Option<HeaderValue>,
+    pub(crate) last_modified: Option<HeaderValue>,
+}
+
+/// Result of a cache lookup.
+pub(crate) enum Lookup {
+    /// The entry is fresh and can be used as is.
+    Fresh(Box<FetchedStellarToml>),
+    /// The entry expired and must be revalidated.
+    Stale(Validators),
+    /// There is no usable entry.
+    Miss,
+}
+
+#[derive(Debug)]
+pub(crate) struct Cache {
+    config: CacheConfig,
+    entries: Mutex<HashMap<String, Entry>>,
+    disk: Option<DiskCache>,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    fetched: FetchedStellarToml,
+    expires_at: SystemTime,
+    validators: Validators,
+}
+
+impl Cache {
+    pub(crate) fn new(config: CacheConfig) -> Cache {
+        let disk = config.directory.clone().map(DiskCache::new);
+        Cache {
+            config,
+            entries: Mutex::new(HashMap::new()),
+            disk,
+        }
+    }
+
+    pub(crate) async fn get(&self, url: &Uri) -> Lookup {
+        match self.entry(url).await {
+            None => Lookup::Miss,
+            Some(entry) if entry.expires_at > SystemTime::now() => {
+                Lookup::Fresh(Box::new(entry.fetched))
+            }
+            Some(entry) if entry.validators.is_empty() => Lookup::Miss,
+            Some(entry) => Lookup::Stale(entry.validators),
+        }
+    }
+
+    /// Returns the entry for `url`, even if expired.
+    pub(crate) async fn get_stale(&self, url: &Uri) -> Option<FetchedStellarToml> {
+        self.entry(url).await.map(|entry| entry.fetched)
+    }
+
+    /// Caches `fetched` according to its response headers.
+    pub(crate) async fn insert(&self, url: &Uri, fetched: &FetchedStellarToml) {
+        let ttl = match self.ttl(&fetched.headers) {
+            None => {
+                self.remove(url).await;
+                return;
+            }
+            Some(ttl) => ttl,
+        };
+        let now = SystemTime::now();
+        let entry = Entry {
+            fetched: fetched.clone(),
+            expires_at: now + ttl,
+            validators: Validators::from_headers(&fetched.headers),
+        };
+        if let Some(disk) = &self.disk {
+            disk.store(url, entry.to_disk(url)).await;
+        }
+        self.insert_memory(url, entry);
+    }
+
+    /// Refreshes the entry for `url` after a `304 Not Modified`
+    /// response with the given `headers`, and returns it.
+    pub(crate) async fn refresh(
+        &self,
+        url: &Uri,
+        headers: &HeaderMap,
+    ) -> Option<FetchedStellarToml> {
+        let mut entry = self.entry(url).await?;
+        let ttl = match self.ttl(headers) {
+            None => {
+                self.remove(url).await;
+                return Some(entry.fetched);
+            }
+            Some(ttl) => ttl,
+        };
+        entry.expires_at = SystemTime::now() + ttl;
+        let validators = Validators::from_headers(headers);
+        if !validators.is_empty() {
+            entry.validators = validators;
+        }
+        if let Some(disk) = &self.disk {
+            disk.store_metadata(url, entry.to_disk(url).metadata).await;
+        }
+        let fetched = entry.fetched.clone();
+        self.insert_memory(url, entry);
+        Some(fetched)
+    }
+
+    /// Returns the entry for `url` from memory or, if missing, from disk.
+    async fn entry(&self, url: &Uri) -> Option<Entry> {
+        if let Some(entry) = self.entries.lock().unwrap().get(&url.to_string()) {
+            return Some(entry.clone());
+        }
+        let entry = Entry::from_disk(self.disk.as_ref()?.load(url).await?)?;
+        self.insert_memory(url, entry.clone());
+        Some(entry)
+    }
+
+    fn insert_memory(&self, url: &Uri, entry: Entry) {
+        let key = url.to_string();
+        let mut entries = self.entries.lock().unwrap();
+        if !entries.contains_key(&key) && entries.len() >= self.config.capacity {
+            evict(&mut entries);
+            if entries.len() >= self.config.capacity {
+                return;
+            }
+        }
+        entries.insert(key, entry);
+    }
+
+    async fn remove(&self, url: &Uri) {
+        self.entries.lock().unwrap().remove(&url.to_string());
+        if let Some(disk) = &self.disk {
+            disk.remove(url).await;
+        }
+    }
+
+    /// Returns how long a response with `headers` can be cached, at
+    /// most one year, or `None` if it must not be cached.
+    fn ttl(&self, headers: &HeaderMap) -> Option<Duration> {
+        let mut ttl = self.config.default_ttl;
+        for value in headers.get_all(CACHE_CONTROL) {
+            let value = match value.to_str() {
+                Ok(value) => value,
+                Err(_) => continue,
+            };
+            for directive in value.split(',') {
+                let directive = directive.trim().to_ascii_lowercase();
+                if directive == "no-store" {
+                    return None;
+                } else if directive == "no-cache" {
+                    ttl = Duration::from_secs(0);
+                } else if let Some(max_age) = directive.strip_prefix("max-age=") {
+                    if let Ok(max_age) = max_age.trim_matches('"').parse() {
+                        ttl = Duration::from_secs(max_age);
+                    }
+                }
+            }
+        }
+        Some(ttl.min(MAX_TTL))
+    }
+}
+
+impl Entry {
+    fn to_disk(&self, url: &Uri) -> DiskEntry {
+        let header_to_string =
+            |value: &Option<HeaderValue>| value.as_ref()?.to_str().ok().map(String::from);
+        let headers = self
+            .fetched
+            .headers
+            .iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        DiskEntry {
+            body: self.fetched.body.clone(),
+            metadata: Metadata {
+                url: url.to_string(),
+                final_url: self.fetched.url.to_string(),
+                redirects: self.fetched.redirects.iter().map(Uri::to_string).collect(),
+                status: self.fetched.status.as_u16(),
+                headers,
+                elapsed_ms: self.fetched.elapsed.as_millis() as u64,
+                peer_certificates: self.fetched.peer_certificates.clone(),
+                fetched_at: to_unix(self.fetched.fetched_at),
+                expires_at: to_unix(self.expires_at),
+                etag: header_to_string(&self.validators.etag),
+                last_modified: header_to_string(&self.validators.last_modified),
+            },
+        }
+    }
+
+    fn from_disk(entry: DiskEntry) -> Option<Entry> {
+        let metadata = entry.metadata;
+        let stellar_toml: StellarToml = toml::from_slice(&entry.body).ok()?;
+        let redirects = metadata
+            .redirects
+            .iter()
+            .map(|url| url.parse())
+            .collect::<Result<_, _>>()
+            .ok()?;
+        let string_to_header =
+            |value: Option<String>| value.and_then(|value| HeaderValue::from_str(&value).ok());
+        let mut headers = HeaderMap::new();
+        for (name, value) in &metadata.headers {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(value),
+            ) {
+                headers.append(name, value);
+            }
+        }
+        let status = StatusCode::from_u16(metadata.status).ok()?;
+        Some(Entry {
+            fetched: FetchedStellarToml {
+                stellar_toml,
+                url: metadata.final_url.parse().ok()?,
+                redirects,
+                peer_certificates: metadata.peer_certificates,
+                status,
+                headers: headers.clone(),
+                body_sha256: Sha256::digest(&entry.body).into(),
+                body: entry.body,
+                elapsed: Duration::from_millis(metadata.elapsed_ms),
+                fetched_at: from_unix(metadata.fetched_at)?,
+                warnings: compliance::check(&headers),
+            },
+            expires_at: from_unix(metadata.expires_at)?,
+            validators: Validators {
+                etag: string_to_header(metadata.etag),
+                last_modified: string_to_header(metadata.last_modified),
+            },
+        })
+    }
+}
+
+impl Validators {
+    fn from_headers(headers: &HeaderMap) -> Validators {
+        Validators {
+            etag: headers.get(ETAG).cloned(),
+            last_modified: headers.get(LAST_MODIFIED).cloned(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+/// Removes expired entries or, if there are none, the entry closest
+/// to expiration.
+fn evict(entries: &mut HashMap<String, Entry>) {
+    let now = SystemTime::now();
+    let len = entries.len();
+    entries.retain(|_, entry| entry.expires_at > now);
+    if entries.len() < len {
+        return;
+    }
+    let oldest = entries
+        .iter()
+        .min_by_key(|(_, entry)| entry.expires_at)
+        .map(|(key, _)| key.clone());
+    if let Some(key) = oldest {
+        entries.remove(&key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Fingerprint, PeerCertificate};
+    use bytes::Bytes;
+    use http::StatusCode;
+
+    fn ttl(cache_control: &str) -> Option<Duration> {
+        let cache = Cache::new(CacheConfig::new().default_ttl(Duration::from_secs(60)));
+        let mut headers = HeaderMap::new();
+        headers.insert(CACHE_CONTROL, cache_control.parse().unwrap());
+        cache.ttl(&headers)
+    }
+
+    #[test]
+    fn test_cache_control_ttl() {
+        assert_eq!(Some(Duration::from_secs(60)), ttl("public"));
+        assert_eq!(Some(Duration::from_secs(120)), ttl("public, max-age=120"));
+        assert_eq!(Some(Duration::from_secs(0)), ttl("no-cache"));
+        assert_eq!(None, ttl("max-age=120, no-store"));
+        assert_eq!(Some(Duration::from_secs(60)), ttl("max-age=abc"));
+        assert_eq!(Some(MAX_TTL), ttl("max-age=18446744073709551615"));
+    }
+
+    #[tokio::test]
+    async fn test_cache_huge_max_age() {
+        let cache = Cache::new(CacheConfig::new());
+        let url: Uri = "https://example.org/.well-known/stellar.toml"
+            .parse()
+            .unwrap();
+        let body = Bytes::from_static(b"VERSION = \"2.0.0\"");
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CACHE_CONTROL,
+            "max-age=18446744073709551615".parse().unwrap(),
+        );
+        let fetched = FetchedStellarToml {
+            stellar_toml: toml::from_slice(&body).unwrap(),
+            url: url.clone(),
+            redirects: vec![],
+            peer_certificates: vec![],
+            status: StatusCode::OK,
+            warnings: compliance::check(&headers),
+            headers: headers.clone(),
+            body_sha256: Sha256::digest(&body).into(),
+            body,
+            elapsed: Duration::from_millis(42),
+            fetched_at: SystemTime::now(),
+        };
+        cache.insert(&url, &fetched).await;
+        assert!(matches!(cache.get(&url).await, Lookup::Fresh(_)));
+        assert!(cache.refresh(&url, &headers).await.is_some());
+    }
+
+    #[test]
+    fn test_from_unix_out_of_range() {
+        let now = SystemTime::now();
+        assert_eq!(Some(to_unix(now)), from_unix(to_unix(now)).map(to_unix));
+        assert_eq!(None, from_unix(u64::MAX));
+    }
+
+    #[test]
+    fn test_disk_metadata_without_certificates() {
+        let url = "http://example.org/.well-known/stellar.toml";
+        let metadata = Metadata {
+            url: url.to_string(),
+            final_url: url.to_string(),
+            redirects: vec![],
+            status: 200,
+            headers: vec![],
+            elapsed_ms: 42,
+            fetched_at: 1,
+            expires_at: 2,
+            etag: None,
+            last_modified: None,
+            peer_certificates: vec![],
+        };
+        let bytes = toml::to_vec(&metadata).unwrap();
+        let metadata: Metadata = toml::from_slice(&bytes).unwrap();
+        assert_eq!(url, metadata.final_url);
+        assert_eq!(200, metadata.status);
+        assert!(metadata.peer_certificates.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_disk_cache_survives_restart() {
+        let directory = std::env::temp_dir().join(format!("stellar-toml-{}", fastrand::u64(..)));
+        let config = CacheConfig::new().directory(&directory);
+        let url: Uri = "https://example.org/.well-known/stellar.toml"
+            .parse()
+            .unwrap();
+        let body = Bytes::from_static(b"VERSION = \"2.0.0\"");
+        let mut headers = HeaderMap::new();
+        headers.insert(ETAG, "\"v1\"".parse().unwrap());
+        let warnings = compliance::check(&headers);
+        let fetched = FetchedStellarToml {
+            stellar_toml: toml::from_slice(&body).unwrap(),
+            url: "https://www.example.org/stellar.toml".parse().unwrap(),
+            redirects: vec![url.clone()],
+            peer_certificates: vec![PeerCertificate {
+                fingerprint: Fingerprint::of(b"certificate"),
+                public_key: Fingerprint::of(b"public key"),
+            }],
+            status: StatusCode::OK,
+            headers,
+            body_sha256: Sha256::digest(&body).into(),
+            body,
+            elapsed: Duration::from_millis(42),
+            fetched_at: SystemTime::now() - Duration::from_secs(10),
+            warnings,
+        };
+        Cache::new(config.clone()).insert(&url, &fetched).await;
+
+        let cache = Cache::new(config);
+        let cached = match cache.get(&url).await {
+            Lookup::Fresh(cached) => cached,
+            _ => panic!("expected fresh entry"),
+        };
+        assert_eq!(Some("2.0.0".to_string()), cached.stellar_toml.version);
+        assert_eq!(fetched.url, cached.url);
+        assert_eq!(fetched.redirects, cached.redirects);
+        assert_eq!(fetched.peer_certificates, cached.peer_certificates);
+        assert_eq!(fetched.status, cached.status);
+        assert_eq!(fetched.headers, cached.headers);
+        assert_eq!(fetched.body, cached.body);
+        assert_eq!(fetched.body_sha256, cached.body_sha256);
+        assert_eq!(fetched.elapsed, cached.elapsed);
+        assert_eq!(to_unix(fetched.fetched_at), to_unix(cached.fetched_at));
+        assert_eq!(fetched.warnings, cached.warnings);
+        let other: Uri = "https://other.example.org/".parse().unwrap();
+        assert!(matches!(cache.get(&other).await, Lookup::Miss));
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
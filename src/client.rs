@@ -0,0 +1,1662 @@
+use crate::cache::{Cache, CacheConfig, Lookup, Validators};
+use crate::compliance::{self, ComplianceWarning};
+use crate::decompress::{accept_encoding, DecodeError, Decoder};
+#[cfg(feature = "hyper-transport")]
+use crate::ip_filter::{IpFilter, IpRange};
+use crate::observer::{CacheStatus, LookupOutcome, ResolverObserver, SharedObserver};
+use crate::pinning::{matches_pins, Fingerprint, PeerCertificate, PeerCertificates};
+#[cfg(feature = "hyper-transport")]
+use crate::proxy::Proxy;
+use crate::rate_limit::{RateLimit, RateLimiter};
+use crate::redirect::{is_redirect, redirect_location, RedirectPolicy};
+use crate::retry::RetryPolicy;
+use crate::single_flight::SingleFlight;
+use crate::timer::{SharedTimer, Timer};
+#[cfg(feature = "hyper-transport")]
+use crate::tls::Certificate;
+use crate::trace::Instrument;
+use crate::transport::{Body, Transport};
+#[cfg(feature = "hyper-transport")]
+use crate::transport::{DnsResolver, HyperTransport, SharedDnsResolver};
+use crate::{
+    stellar_toml_path, Error, ErrorResponse, StellarToml, TimeoutPhase, DEFAULT_USER_AGENT,
+    ERROR_BODY_EXCERPT_SIZE, STELLAR_TOML_MAX_SIZE,
+};
+use bytes::Bytes;
+use http::header::{
+    HeaderName, HeaderValue, ACCEPT_ENCODING, CONTENT_LENGTH, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+    USER_AGENT,
+};
+use http::uri::Uri;
+use http::{HeaderMap, Request, StatusCode};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::future::Future;
+#[cfg(feature = "hyper-transport")]
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Maximum size of the body of a redirect response read before
+/// following it, so that the connection can be reused.
+const REDIRECT_BODY_MAX_SIZE: usize = 8 * 1024;
+
+/// A client used to resolve `stellar.toml` files.
+///
+/// The client is generic over the `Transport` used to send requests,
+/// by default it uses a `HyperTransport` that owns a pool of
+/// connections reused across lookups, so it should be created once
+/// and shared. Cloning the client is cheap and all clones share the
+/// same connection pool and cache.
+///
+/// Concurrent lookups of the same url are coalesced, so that only one
+/// request is sent and all callers receive a clone of its result.
+#[cfg(feature = "hyper-transport")]
+#[derive(Debug)]
+pub struct StellarTomlClient<T = HyperTransport> {
+    transport: Arc<T>,
+    config: Arc<Config>,
+    cache: Option<Arc<Cache>>,
+    inflight: Arc<SingleFlight>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+}
+
+/// A client used to resolve `stellar.toml` files.
+///
+/// Without the `hyper-transport` feature the client has no default
+/// transport, create it with `ClientBuilder::build_with_transport`.
+#[cfg(not(feature = "hyper-transport"))]
+#[derive(Debug)]
+pub struct StellarTomlClient<T> {
+    transport: Arc<T>,
+    config: Arc<Config>,
+    cache: Option<Arc<Cache>>,
+    inflight: Arc<SingleFlight>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+}
+
+/// Builder used to configure a `StellarTomlClient`.
+///
+#[cfg_attr(feature = "hyper-transport", doc = "```rust")]
+#[cfg_attr(not(feature = "hyper-transport"), doc = "```ignore")]
+/// use std::time::Duration;
+/// use stellar_toml::StellarTomlClient;
+///
+/// let client = StellarTomlClient::builder()
+///     .connect_timeout(Duration::from_secs(5))
+///     .timeout(Duration::from_secs(30))
+///     .build();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ClientBuilder {
+    config: Config,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Config {
+    pub(crate) connect_timeout: Option<Duration>,
+    pub(crate) first_byte_timeout: Option<Duration>,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) max_body_size: usize,
+    pub(crate) headers: HeaderMap,
+    pub(crate) redirect_policy: RedirectPolicy,
+    pub(crate) retry_policy: Option<RetryPolicy>,
+    pub(crate) cache: Option<CacheConfig>,
+    pub(crate) offline: bool,
+    pub(crate) strict: bool,
+    pub(crate) rate_limit: Option<RateLimit>,
+    pub(crate) timer: SharedTimer,
+    pub(crate) observer: Option<SharedObserver>,
+    #[cfg(feature = "hyper-transport")]
+    pub(crate) proxy: Option<Proxy>,
+    #[cfg(feature = "hyper-transport")]
+    pub(crate) root_certificates: Vec<Certificate>,
+    #[cfg(feature = "hyper-transport")]
+    pub(crate) built_in_root_certs: bool,
+    pub(crate) public_key_pins: HashMap<String, Vec<Fingerprint>>,
+    #[cfg(feature = "hyper-transport")]
+    pub(crate) ip_filter: IpFilter,
+    #[cfg(feature = "hyper-transport")]
+    pub(crate) dns_resolver: Option<SharedDnsResolver>,
+    #[cfg(feature = "hyper-transport")]
+    pub(crate) host_overrides: HashMap<String, SocketAddr>,
+}
+
+/// A parsed `stellar.toml` file, together with information about
+/// how it was fetched.
+#[derive(Debug, Clone)]
+pub struct FetchedStellarToml {
+    /// The parsed `stellar.toml` file.
+    pub stellar_toml: StellarToml,
+    /// The url the file was fetched from, after following redirects.
+    pub url: Uri,
+    /// The urls that responded with a redirect, in the order they
+    /// were visited.
+    pub redirects: Vec<Uri>,
+    /// The certificate chain sent by the server of `url`, starting
+    /// with its own certificate, or empty if the file was not fetched
+    /// with https.
+    ///
+    /// With the `native-tls` feature only the server certificate is
+    /// available.
+    pub peer_certificates: Vec<PeerCertificate>,
+    /// The status code of the response.
+    pub status: StatusCode,
+    /// The headers of the response.
+    pub headers: HeaderMap,
+    /// The raw body of the response, decoded if it was compressed.
+    pub body: Bytes,
+    /// The SHA-256 hash of `body`.
+    pub body_sha256: [u8; 32],
+    /// How long it took to fetch the file, including redirects.
+    pub elapsed: Duration,
+    /// When the file was fetched. Files served from the cache keep
+    /// the time they were originally fetched.
+    pub fetched_at: SystemTime,
+    /// The SEP-1 requirements that the response does not meet.
+    pub warnings: Vec<ComplianceWarning>,
+}
+
+/// Outcome of fetching a `stellar.toml` file.
+enum Fetch {
+    /// The file was fetched and parsed.
+    Modified(Box<FetchedStellarToml>),
+    /// The server confirmed that the cached file is still valid.
+    NotModified(HeaderMap),
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            connect_timeout: None,
+            first_byte_timeout: None,
+            timeout: None,
+            max_body_size: STELLAR_TOML_MAX_SIZE,
+            headers: default_headers(),
+            redirect_policy: RedirectPolicy::default(),
+            retry_policy: None,
+            cache: None,
+            offline: false,
+            strict: false,
+            rate_limit: None,
+            timer: SharedTimer::default(),
+            observer: None,
+            #[cfg(feature = "hyper-transport")]
+            proxy: None,
+            #[cfg(feature = "hyper-transport")]
+            root_certificates: Vec::new(),
+            #[cfg(feature = "hyper-transport")]
+            built_in_root_certs: true,
+            public_key_pins: HashMap::new(),
+            #[cfg(feature = "hyper-transport")]
+            ip_filter: IpFilter::default(),
+            #[cfg(feature = "hyper-transport")]
+            dns_resolver: None,
+            #[cfg(feature = "hyper-transport")]
+            host_overrides: HashMap::new(),
+        }
+    }
+}
+
+#[cfg(feature = "hyper-transport")]
+impl StellarTomlClient<HyperTransport> {
+    /// Creates a new client with the default configuration.
+    pub fn new() -> StellarTomlClient<HyperTransport> {
+        ClientBuilder::new().build()
+    }
+
+    /// Returns a builder to configure a new client.
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::new()
+    }
+}
+
+impl<T> StellarTomlClient<T>
+where
+    T: Transport,
+{
+    /// Creates a new client that sends requests using `transport`.
+    pub fn with_transport(transport: T) -> StellarTomlClient<T> {
+        ClientBuilder::new().build_with_transport(transport)
+    }
+
+    /// Returns a reference to the client transport.
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    /// Returns a parsed `stellar.toml` file at `domain`.
+    ///
+    /// This method will always fetch the `stellar.toml` file using https.
+    /// See `resolve` for more information.
+    pub async fn resolve(&self, domain: &str) -> Result<StellarToml, Error> {
+        let url = stellar_toml_path(domain)?;
+        self.resolve_url(&url).await
+    }
+
+    /// Returns a parsed `stellar.toml` file at `url`.
+    pub async fn resolve_url(&self, url: &Uri) -> Result<StellarToml, Error> {
+        let fetched = self.resolve_url_with_metadata(url).await?;
+        Ok(fetched.stellar_toml)
+    }
+
+    /// Returns a parsed `stellar.toml` file at `domain`, together with
+    /// information about how it was fetched.
+    pub async fn resolve_with_metadata(&self, domain: &str) -> Result<FetchedStellarToml, Error> {
+        let url = stellar_toml_path(domain)?;
+        self.resolve_url_with_metadata(&url).await
+    }
+
+    /// Returns a parsed `stellar.toml` file at `url`, together with
+    /// information about how it was fetched.
+    pub async fn resolve_url_with_metadata(&self, url: &Uri) -> Result<FetchedStellarToml, Error> {
+        let client = self.clone();
+        let url = url.clone();
+        let span = trace_span!("resolve", domain = %url.host().unwrap_or_default(), url = %url);
+        self.inflight
+            .run(url.to_string(), move || async move {
+                let start = Instant::now();
+                let result = with_timeout(
+                    &client.config.timer,
+                    client.config.timeout,
+                    TimeoutPhase::Total,
+                    &url,
+                    client.resolve_cached(&url),
+                )
+                .await;
+                let (result, cache) = match result {
+                    Ok((fetched, cache)) => (client.check_compliance(fetched), cache),
+                    Err(err) => (Err(err), CacheStatus::Miss),
+                };
+                if let Some(SharedObserver(observer)) = &client.config.observer {
+                    observer.on_lookup(&LookupOutcome {
+                        url: &url,
+                        elapsed: start.elapsed(),
+                        cache,
+                        result: result.as_ref(),
+                    });
+                }
+                #[cfg(feature = "tracing")]
+                match &result {
+                    Ok(fetched) => trace_event!(
+                        debug,
+                        status = fetched.status.as_u16(),
+                        bytes = fetched.body.len(),
+                        warnings = fetched.warnings.len(),
+                        "resolved"
+                    ),
+                    Err(err) => trace_event!(
+                        warn,
+                        error.kind = err.kind(),
+                        error = %err,
+                        "resolution failed"
+                    ),
+                }
+                result
+            })
+            .instrument(span)
+            .await
+    }
+
+    /// Rejects `fetched` if it has warnings and the client is in strict
+    /// mode. Cached files are checked too, since they may have been
+    /// cached by a client that is not strict.
+    fn check_compliance(&self, fetched: FetchedStellarToml) -> Result<FetchedStellarToml, Error> {
+        if self.config.strict && !fetched.warnings.is_empty() {
+            return Err(Error::NonCompliant {
+                warnings: fetched.warnings,
+                url: fetched.url,
+            });
+        }
+        Ok(fetched)
+    }
+
+    /// Returns the file at `url`, from the cache if possible, and
+    /// whether it was served from the cache.
+    async fn resolve_cached(&self, url: &Uri) -> Result<(FetchedStellarToml, CacheStatus), Error> {
+        if self.config.offline {
+            let cached = match &self.cache {
+                Some(cache) => cache.get_stale(url).await,
+                None => None,
+            };
+            return cached
+                .map(|fetched| (fetched, CacheStatus::Hit))
+                .ok_or_else(|| Error::NotCached { url: url.clone() });
+        }
+        let lookup = match &self.cache {
+            Some(cache) => cache.get(url).await,
+            None => Lookup::Miss,
+        };
+        let mut validators = match lookup {
+            Lookup::Fresh(fetched) => {
+                trace_event!(debug, "cache hit");
+                return Ok((*fetched, CacheStatus::Hit));
+            }
+            Lookup::Stale(validators) => Some(validators),
+            Lookup::Miss => None,
+        };
+        loop {
+            match self.fetch_with_retry(url, validators.as_ref()).await? {
+                Fetch::Modified(fetched) => {
+                    if let Some(cache) = &self.cache {
+                        cache.insert(url, &fetched).await;
+                    }
+                    return Ok((*fetched, CacheStatus::Miss));
+                }
+                Fetch::NotModified(headers) => {
+                    trace_event!(debug, "cache revalidated");
+                    let cached = match &self.cache {
+                        Some(cache) => cache.refresh(url, &headers).await,
+                        None => None,
+                    };
+                    match cached {
+                        Some(fetched) => return Ok((fetched, CacheStatus::Revalidated)),
+                        // The entry was evicted while revalidating it.
+                        None => validators = None,
+                    }
+                }
+            }
+        }
+    }
+
+    async fn fetch_with_retry(
+        &self,
+        url: &Uri,
+        validators: Option<&Validators>,
+    ) -> Result<Fetch, Error> {
+        let policy = match &self.config.retry_policy {
+            None => return self.fetch(url, validators).await,
+            Some(policy) => policy,
+        };
+        let mut attempt = 1;
+        loop {
+            match self.fetch(url, validators).await {
+                Ok(fetched) => return Ok(fetched),
+                Err(err) if policy.should_retry(attempt, &err) => {
+                    let delay = policy.delay(attempt, &err);
+                    trace_event!(
+                        debug,
+                        attempt,
+                        delay_ms = delay.as_millis() as u64,
+                        error.kind = err.kind(),
+                        error = %err,
+                        "retrying"
+                    );
+                    self.config.timer.sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(err) if attempt > 1 => {
+                    return Err(Error::RetryFailed {
+                        attempts: attempt,
+                        error: Box::new(err),
+                    })
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    async fn fetch(&self, original: &Uri, validators: Option<&Validators>) -> Result<Fetch, Error> {
+        let mut url = original.clone();
+        let mut redirects = Vec::new();
+        let start = Instant::now();
+        let fetched_at = SystemTime::now();
+        loop {
+            let mut request = Request::new(());
+            *request.uri_mut() = url.clone();
+            *request.headers_mut() = self.config.headers.clone();
+            if !request.headers().contains_key(ACCEPT_ENCODING) {
+                if let Some(encodings) = accept_encoding() {
+                    request.headers_mut().insert(ACCEPT_ENCODING, encodings);
+                }
+            }
+            if let Some(validators) = validators {
+                let headers = request.headers_mut();
+                if let Some(etag) = &validators.etag {
+                    headers.insert(IF_NONE_MATCH, etag.clone());
+                }
+                if let Some(last_modified) = &validators.last_modified {
+                    headers.insert(IF_MODIFIED_SINCE, last_modified.clone());
+                }
+            }
+            if let Some(rate_limiter) = &self.rate_limiter {
+                let host = url.host().unwrap_or_default();
+                rate_limiter.acquire(host, &self.config.timer).await;
+            }
+            let response = with_timeout(
+                &self.config.timer,
+                self.config.first_byte_timeout,
+                TimeoutPhase::FirstByte,
+                &url,
+                self.transport.send(request),
+            )
+            .instrument(trace_span!("request", url = %url))
+            .await?;
+            trace_event!(debug, status = response.status().as_u16(), url = %url, "response");
+
+            let peer_certificates = response
+                .extensions()
+                .get::<PeerCertificates>()
+                .map(|chain| chain.0.clone())
+                .unwrap_or_default();
+            let host = url.host().unwrap_or_default().to_ascii_lowercase();
+            if let Some(pins) = self.config.public_key_pins.get(&host) {
+                if !matches_pins(pins, &peer_certificates) {
+                    return Err(Error::CertificatePinMismatch { url });
+                }
+            }
+
+            if is_redirect(response.status()) {
+                let location = redirect_location(&url, response.headers())?;
+                trace_event!(debug, location = %location, "redirect");
+                self.config
+                    .redirect_policy
+                    .check(original, &url, &location, redirects.len())?;
+                drain_body(response.into_body(), REDIRECT_BODY_MAX_SIZE).await;
+                redirects.push(url);
+                url = location;
+            } else if response.status().is_success() {
+                let limit = self.config.max_body_size;
+                let (parts, body) = response.into_parts();
+                let bytes = read_body(body, &parts.headers, limit, &url)
+                    .instrument(trace_span!("read_body", url = %url))
+                    .await?;
+                let stellar_toml = parse(&bytes, &url)?;
+                let warnings = compliance::check(&parts.headers);
+                let fetched = FetchedStellarToml {
+                    stellar_toml,
+                    url,
+                    redirects,
+                    peer_certificates,
+                    status: parts.status,
+                    headers: parts.headers,
+                    body_sha256: Sha256::digest(&bytes).into(),
+                    body: bytes,
+                    elapsed: start.elapsed(),
+                    fetched_at,
+                    warnings,
+                };
+                return Ok(Fetch::Modified(Box::new(fetched)));
+            } else if response.status() == StatusCode::NOT_MODIFIED && validators.is_some() {
+                return Ok(Fetch::NotModified(response.into_parts().0.headers));
+            } else {
+                let (parts, body) = response.into_parts();
+                let body = read_excerpt(body, &parts.headers).await;
+                let response =
+                    Box::new(ErrorResponse::new(parts.status, url, parts.headers, &body));
+                if parts.status.is_client_error() {
+                    return Err(Error::ClientError(response));
+                }
+                return Err(Error::ServerError(response));
+            }
+        }
+    }
+}
+
+impl<T> Clone for StellarTomlClient<T> {
+    fn clone(&self) -> StellarTomlClient<T> {
+        StellarTomlClient {
+            transport: self.transport.clone(),
+            config: self.config.clone(),
+            cache: self.cache.clone(),
+            inflight: self.inflight.clone(),
+            rate_limiter: self.rate_limiter.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "hyper-transport")]
+impl Default for StellarTomlClient<HyperTransport> {
+    fn default() -> StellarTomlClient<HyperTransport> {
+        StellarTomlClient::new()
+    }
+}
+
+impl ClientBuilder {
+    /// Creates a new builder with the default configuration.
+    ///
+    /// By default the client has no timeouts.
+    pub fn new() -> ClientBuilder {
+        ClientBuilder::default()
+    }
+
+    /// Sets the maximum time to establish a connection with the host.
+    ///
+    /// When the request goes through a proxy, the timeout covers both
+    /// the connection to the proxy and the proxy handshake.
+    ///
+    /// This timeout is enforced by the `HyperTransport` created by
+    /// `build`, custom transports are responsible for their own
+    /// connection timeouts.
+    pub fn connect_timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.config.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the maximum time to wait for the response headers, after
+    /// the request is sent.
+    pub fn first_byte_timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.config.first_byte_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the maximum time to resolve a `stellar.toml` file,
+    /// including reading and parsing the response body.
+    pub fn timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.config.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the maximum size, in bytes, of the `stellar.toml` file.
+    ///
+    /// Defaults to `STELLAR_TOML_MAX_SIZE`, the limit defined by SEP-1.
+    /// The limit applies to the decoded file, if the response is
+    /// compressed.
+    pub fn max_body_size(mut self, limit: usize) -> ClientBuilder {
+        self.config.max_body_size = limit;
+        self
+    }
+
+    /// Sets the `User-Agent` header sent with requests.
+    ///
+    /// Defaults to `DEFAULT_USER_AGENT`, `stellar-toml-rs/` followed by
+    /// the crate version.
+    ///
+    #[cfg_attr(feature = "hyper-transport", doc = "```rust")]
+    #[cfg_attr(not(feature = "hyper-transport"), doc = "```ignore")]
+    /// use http::header::HeaderValue;
+    /// use stellar_toml::StellarTomlClient;
+    ///
+    /// let client = StellarTomlClient::builder()
+    ///     .user_agent(HeaderValue::from_static("my-crawler/1.0"))
+    ///     .build();
+    /// ```
+    pub fn user_agent(mut self, value: HeaderValue) -> ClientBuilder {
+        self.config.headers.insert(USER_AGENT, value);
+        self
+    }
+
+    /// Adds a header sent with every request.
+    ///
+    /// Calling this method multiple times with the same `name` sends
+    /// all the values. The header is also sent when following
+    /// redirects, even to a different host.
+    ///
+    /// By default the client sends an `Accept-Encoding` header with
+    /// the encodings enabled by the crate features. Setting the header
+    /// replaces it, for example with `identity` to disable compression.
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> ClientBuilder {
+        self.config.headers.append(name, value);
+        self
+    }
+
+    /// Sets the policy used to follow redirects.
+    ///
+    /// By default redirects are not followed.
+    pub fn redirect_policy(mut self, policy: RedirectPolicy) -> ClientBuilder {
+        self.config.redirect_policy = policy;
+        self
+    }
+
+    /// Sets the policy used to retry lookups that failed with a
+    /// transient error.
+    ///
+    /// By default lookups are not retried.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> ClientBuilder {
+        self.config.retry_policy = Some(policy);
+        self
+    }
+
+    /// Enables the in-memory cache of resolved `stellar.toml` files.
+    ///
+    /// By default files are not cached.
+    pub fn cache(mut self, config: CacheConfig) -> ClientBuilder {
+        self.config.cache = Some(config);
+        self
+    }
+
+    /// Sets whether to resolve `stellar.toml` files only from the cache.
+    ///
+    /// In offline mode the client never sends requests, and serves
+    /// cached files even if expired. Lookups of files that were
+    /// never cached fail with `Error::NotCached`.
+    pub fn offline(mut self, offline: bool) -> ClientBuilder {
+        self.config.offline = offline;
+        self
+    }
+
+    /// Sets whether to reject files that don't meet the SEP-1
+    /// requirements on the response headers.
+    ///
+    /// By default these problems are only reported in
+    /// `FetchedStellarToml::warnings`. In strict mode lookups fail with
+    /// `Error::NonCompliant` instead, for example to make sure that
+    /// browser applications can read the file.
+    pub fn strict(mut self, strict: bool) -> ClientBuilder {
+        self.config.strict = strict;
+        self
+    }
+
+    /// Limits the rate of requests sent to each host.
+    ///
+    /// The limit applies to all requests sent by the client and its
+    /// clones, including redirects, retries and revalidations.
+    pub fn rate_limit(mut self, limit: RateLimit) -> ClientBuilder {
+        self.config.rate_limit = Some(limit);
+        self
+    }
+
+    /// Sets the timer used to enforce timeouts and to delay retries
+    /// and rate limited requests.
+    ///
+    /// Defaults to `TokioTimer` when the `tokio` dependency is enabled,
+    /// and to `ThreadTimer` otherwise. Use a timer that works with the
+    /// executor that runs the client.
+    pub fn timer<T: Timer>(mut self, timer: T) -> ClientBuilder {
+        self.config.timer = SharedTimer(Arc::new(timer));
+        self
+    }
+
+    /// Sets the observer notified of the outcome of every lookup.
+    ///
+    /// Use the `MetricsObserver`, with the `metrics` feature, to record
+    /// lookups with the `metrics` facade.
+    pub fn observer<O: ResolverObserver>(mut self, observer: O) -> ClientBuilder {
+        self.config.observer = Some(SharedObserver(Arc::new(observer)));
+        self
+    }
+
+    /// Sends requests through `proxy`.
+    ///
+    /// Like the connect timeout, the proxy is used by the
+    /// `HyperTransport` created by `build`. Use `Proxy::from_env` to
+    /// configure the proxy from the environment.
+    #[cfg(feature = "hyper-transport")]
+    pub fn proxy(mut self, proxy: Proxy) -> ClientBuilder {
+        self.config.proxy = Some(proxy);
+        self
+    }
+
+    /// Adds a root certificate trusted when verifying https
+    /// connections, in addition to the built-in ones.
+    ///
+    /// Like the proxy, root certificates are used by the
+    /// `HyperTransport` created by `build`.
+    #[cfg(feature = "hyper-transport")]
+    pub fn add_root_certificate(mut self, certificate: Certificate) -> ClientBuilder {
+        self.config.root_certificates.push(certificate);
+        self
+    }
+
+    /// Sets whether to trust the built-in root certificates. Defaults to `true`.
+    ///
+    /// The built-in root certificates are the system ones with the
+    /// `native-tls` feature, and the Mozilla ones with `rustls-tls`.
+    /// Disable them to trust only the certificates added with
+    /// `add_root_certificate`.
+    #[cfg(feature = "hyper-transport")]
+    pub fn tls_built_in_root_certs(mut self, enabled: bool) -> ClientBuilder {
+        self.config.built_in_root_certs = enabled;
+        self
+    }
+
+    /// Pins the public key of the certificates of `domain`.
+    ///
+    /// Requests to a domain with pinned keys fail with
+    /// `Error::CertificatePinMismatch` unless one of the certificates
+    /// sent by the server has one of the pinned public keys. Call this
+    /// method multiple times to pin backup keys. Requests sent over
+    /// http, or with a transport that doesn't report the server
+    /// certificates, always fail.
+    ///
+    /// With the `native-tls` feature only the server certificate is
+    /// checked, so the pin must be the key of the leaf certificate.
+    pub fn pin_public_key(mut self, domain: &str, fingerprint: Fingerprint) -> ClientBuilder {
+        self.config
+            .public_key_pins
+            .entry(domain.to_ascii_lowercase())
+            .or_default()
+            .push(fingerprint);
+        self
+    }
+
+    /// Sets whether to refuse connecting to private addresses.
+    /// Defaults to `true`.
+    ///
+    /// When enabled, the client does not connect to loopback,
+    /// private, link-local, unique local and other non public
+    /// addresses, such as the `169.254.169.254` metadata service of
+    /// cloud providers. This prevents resolving a domain controlled by
+    /// a third party from reaching internal services. Lookups of hosts
+    /// that resolve only to such addresses fail with
+    /// `Error::BlockedAddress`.
+    ///
+    /// The check applies to the `HyperTransport` created by `build`,
+    /// to direct connections and to connections through a `socks5`
+    /// proxy, which is sent the address resolved by the client.
+    /// `http` and `socks5h` proxies resolve host names themselves, so
+    /// only urls with an ip address host are checked: use a `socks5`
+    /// proxy, or restrict the destinations of the proxy, to keep
+    /// domains from reaching internal services.
+    #[cfg(feature = "hyper-transport")]
+    pub fn block_private_addresses(mut self, block: bool) -> ClientBuilder {
+        self.config.ip_filter.block_private = block;
+        self
+    }
+
+    /// Allows connecting to the addresses in `range`, even if private.
+    ///
+    /// ```rust
+    /// use stellar_toml::StellarTomlClient;
+    ///
+    /// # fn run() -> std::result::Result<(), stellar_toml::Error> {
+    /// // Allow resolving a local test server.
+    /// let client = StellarTomlClient::builder()
+    ///     .allow_ip_range("127.0.0.1".parse()?)
+    ///     .build();
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "hyper-transport")]
+    pub fn allow_ip_range(mut self, range: IpRange) -> ClientBuilder {
+        self.config.ip_filter.allowed.push(range);
+        self
+    }
+
+    /// Resolves hosts with `resolver` instead of the system resolver.
+    ///
+    /// The addresses returned by `resolver` are subject to
+    /// `block_private_addresses`. Like the proxy, the resolver is used
+    /// by the `HyperTransport` created by `build`.
+    #[cfg(feature = "hyper-transport")]
+    pub fn dns_resolver<R: DnsResolver>(mut self, resolver: R) -> ClientBuilder {
+        self.config.dns_resolver = Some(SharedDnsResolver(Arc::new(resolver)));
+        self
+    }
+
+    /// Connects to `address` when sending requests to `domain`,
+    /// without resolving it.
+    ///
+    /// Requests keep `domain` in the `Host` header and in the tls
+    /// server name, so that certificates are verified against it.
+    /// Overridden addresses are trusted and not subject to
+    /// `block_private_addresses`. They don't apply to requests sent
+    /// through a proxy.
+    ///
+    /// ```rust
+    /// use stellar_toml::StellarTomlClient;
+    ///
+    /// let client = StellarTomlClient::builder()
+    ///     .override_host("stellar.example.org", "127.0.0.1:8443".parse().unwrap())
+    ///     .build();
+    /// ```
+    #[cfg(feature = "hyper-transport")]
+    pub fn override_host(mut self, domain: &str, address: SocketAddr) -> ClientBuilder {
+        self.config
+            .host_overrides
+            .insert(domain.to_ascii_lowercase(), address);
+        self
+    }
+
+    /// Builds a client that uses a `HyperTransport`.
+    #[cfg(feature = "hyper-transport")]
+    pub fn build(self) -> StellarTomlClient<HyperTransport> {
+        let transport = HyperTransport::from_config(&self.config);
+        self.build_with_transport(transport)
+    }
+
+    /// Builds a client that sends requests using `transport`.
+    pub fn build_with_transport<T: Transport>(self, transport: T) -> StellarTomlClient<T> {
+        let cache = self
+            .config
+            .cache
+            .clone()
+            .map(|config| Arc::new(Cache::new(config)));
+        let rate_limiter = self
+            .config
+            .rate_limit
+            .clone()
+            .map(|limit| Arc::new(RateLimiter::new(limit)));
+        StellarTomlClient {
+            transport: Arc::new(transport),
+            config: Arc::new(self.config),
+            cache,
+            inflight: Arc::new(SingleFlight::default()),
+            rate_limiter,
+        }
+    }
+}
+
+fn default_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(USER_AGENT, HeaderValue::from_static(DEFAULT_USER_AGENT));
+    headers
+}
+
+/// Reads and decodes `body`, failing as soon as the decoded body
+/// exceeds `limit` bytes.
+async fn read_body(
+    mut body: Body,
+    headers: &HeaderMap,
+    limit: usize,
+    url: &Uri,
+) -> Result<Bytes, Error> {
+    let decode_error = |err| match err {
+        DecodeError::TooLarge => Error::ResponseTooLarge {
+            limit,
+            url: url.clone(),
+        },
+        DecodeError::Invalid(reason) => Error::InvalidContentEncoding {
+            reason,
+            url: url.clone(),
+        },
+    };
+    let mut decoder = Decoder::new(headers, limit).map_err(decode_error)?;
+    let content_length = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    if let Some(content_length) = content_length {
+        // The length of an encoded body says little about its
+        // decoded size.
+        if decoder.is_identity() && content_length > limit as u64 {
+            return Err(decode_error(DecodeError::TooLarge));
+        }
+    }
+    while let Some(chunk) = body.chunk().await {
+        decoder.write(&chunk?).map_err(decode_error)?;
+    }
+    let bytes = decoder.finish().map_err(decode_error)?;
+    trace_event!(debug, bytes = bytes.len(), "body read");
+    Ok(Bytes::from(bytes))
+}
+
+/// Reads `body` to the end, unless it's larger than `limit` bytes, so
+/// that the connection is returned to the pool.
+///
+/// The body is discarded, so failing to read it is not an error.
+async fn drain_body(mut body: Body, limit: usize) {
+    let mut len = 0;
+    while let Some(Ok(chunk)) = body.chunk().await {
+        len += chunk.len();
+        if len > limit {
+            break;
+        }
+    }
+}
+
+/// Reads the beginning of the body of an unsuccessful response, at
+/// most `ERROR_BODY_EXCERPT_SIZE` bytes once decoded, and drops the
+/// rest.
+///
+/// The body is only kept as an excerpt in the error, so failing to
+/// read or decode it is not an error.
+async fn read_excerpt(mut body: Body, headers: &HeaderMap) -> Vec<u8> {
+    let mut decoder = match Decoder::new(headers, ERROR_BODY_EXCERPT_SIZE) {
+        Ok(decoder) => decoder,
+        Err(_) => return Vec::new(),
+    };
+    while let Some(Ok(chunk)) = body.chunk().await {
+        if decoder.write(&chunk).is_err() {
+            break;
+        }
+    }
+    decoder.into_prefix()
+}
+
+/// Parses the body of the `stellar.toml` file at `url`.
+fn parse(bytes: &[u8], url: &Uri) -> Result<StellarToml, Error> {
+    let _span = trace_span!("parse", bytes = bytes.len()).entered();
+    toml::from_slice(bytes).map_err(|source| {
+        trace_event!(debug, error.kind = "toml", error = %source, "invalid stellar.toml");
+        Error::TomlParseError {
+            url: url.clone(),
+            source,
+        }
+    })
+}
+
+async fn with_timeout<F, R>(
+    timer: &SharedTimer,
+    timeout: Option<Duration>,
+    phase: TimeoutPhase,
+    url: &Uri,
+    future: F,
+) -> Result<R, Error>
+where
+    F: Future<Output = Result<R, Error>>,
+{
+    match timeout {
+        None => future.await,
+        Some(timeout) => match timer.timeout(timeout, future).await {
+            Some(result) => result,
+            None => Err(Error::Timeout {
+                phase,
+                url: url.clone(),
+            }),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{Body, MemoryResponse, MemoryTransport, ResponseFuture};
+    use futures_util::stream::{self, StreamExt};
+    use http::header::ACCEPT;
+    use http::StatusCode;
+    use std::sync::Mutex;
+
+    const URL: &str = "https://example.org/.well-known/stellar.toml";
+
+    #[tokio::test]
+    async fn test_resolve_with_memory_transport() {
+        let transport = MemoryTransport::new().with_response(
+            URL,
+            MemoryResponse::ok("VERSION = \"2.0.0\"\nACCOUNTS = [\"GABC\"]\n"),
+        );
+        let client = StellarTomlClient::with_transport(transport);
+        let stellar = client.resolve("example.org").await.unwrap();
+        assert_eq!(Some("2.0.0".to_string()), stellar.version);
+        assert_eq!(vec!["GABC".to_string()], stellar.accounts);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_sends_headers() {
+        let transport =
+            MemoryTransport::new().with_response(URL, MemoryResponse::ok("VERSION = \"2.0.0\""));
+        let client = StellarTomlClient::with_transport(transport.clone());
+        client.resolve("example.org").await.unwrap();
+        let requests = transport.requests();
+        let user_agent = requests[0].headers().get(USER_AGENT).unwrap();
+        assert_eq!(DEFAULT_USER_AGENT, user_agent);
+        assert!(DEFAULT_USER_AGENT.starts_with("stellar-toml-rs/"));
+
+        let client = ClientBuilder::new()
+            .user_agent(HeaderValue::from_static("crawler/1.0"))
+            .header(ACCEPT, HeaderValue::from_static("text/plain"))
+            .header(ACCEPT, HeaderValue::from_static("*/*"))
+            .build_with_transport(transport.clone());
+        client.resolve("example.org").await.unwrap();
+        let requests = transport.requests();
+        let headers = requests[1].headers();
+        assert_eq!("crawler/1.0", headers.get(USER_AGENT).unwrap());
+        let accept: Vec<_> = headers.get_all(ACCEPT).iter().collect();
+        assert_eq!(vec!["text/plain", "*/*"], accept);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[tokio::test]
+    async fn test_resolve_gzip_body() {
+        use std::io::Write;
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(b"VERSION = \"2.0.0\"").unwrap();
+        let body = encoder.finish().unwrap();
+        let response = MemoryResponse::ok(body).with_header("content-encoding", "gzip");
+        let transport = MemoryTransport::new().with_response(URL, response);
+        let client = StellarTomlClient::with_transport(transport.clone());
+        let stellar = client.resolve("example.org").await.unwrap();
+        assert_eq!(Some("2.0.0".to_string()), stellar.version);
+        let requests = transport.requests();
+        let accept_encoding = requests[0].headers().get(ACCEPT_ENCODING).unwrap();
+        assert!(accept_encoding.to_str().unwrap().contains("gzip"));
+
+        let response = MemoryResponse::ok("not gzip").with_header("content-encoding", "gzip");
+        transport.insert(URL, response);
+        let client = StellarTomlClient::with_transport(transport);
+        let result = client.resolve("example.org").await;
+        assert!(matches!(result, Err(Error::InvalidContentEncoding { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_status_errors() {
+        let transport = MemoryTransport::new().with_response(
+            URL,
+            MemoryResponse::new(StatusCode::SERVICE_UNAVAILABLE, "down for maintenance"),
+        );
+        let client = StellarTomlClient::with_transport(transport.clone());
+        let err = client.resolve("example.org").await.unwrap_err();
+        match &err {
+            Error::ServerError(response) => {
+                assert_eq!(StatusCode::SERVICE_UNAVAILABLE, response.status());
+                assert_eq!(URL, response.url());
+                assert_eq!("down for maintenance", response.body_excerpt());
+            }
+            other => panic!("expected server error, got {:?}", other),
+        }
+        assert_eq!(
+            format!("server error: 503 Service Unavailable from {}", URL),
+            err.to_string()
+        );
+        assert_eq!(Some(StatusCode::SERVICE_UNAVAILABLE), err.status());
+        assert_eq!(Some(URL), err.url().map(Uri::to_string).as_deref());
+        assert!(err.is_retryable());
+        assert!(!err.is_not_found());
+
+        let err = client.resolve("missing.example.org").await.unwrap_err();
+        match &err {
+            Error::ClientError(response) => {
+                assert_eq!(StatusCode::NOT_FOUND, response.status());
+                assert_eq!("", response.body_excerpt());
+            }
+            other => panic!("expected client error, got {:?}", other),
+        }
+        assert!(err.is_not_found());
+        assert!(!err.is_retryable());
+
+        let body = format!("a{}", "é".repeat(ERROR_BODY_EXCERPT_SIZE));
+        transport.insert(URL, MemoryResponse::new(StatusCode::FORBIDDEN, body));
+        match client.resolve("example.org").await {
+            Err(Error::ClientError(response)) => {
+                let excerpt = response.body_excerpt();
+                assert_eq!(ERROR_BODY_EXCERPT_SIZE - 1, excerpt.len());
+                assert!(excerpt.ends_with("éé"));
+            }
+            other => panic!("expected client error, got {:?}", other),
+        }
+    }
+
+    /// Responds with `500 Internal Server Error` and a body that never
+    /// ends.
+    #[derive(Debug, Clone)]
+    struct EndlessErrorTransport;
+
+    impl Transport for EndlessErrorTransport {
+        fn send(&self, _request: Request<()>) -> ResponseFuture {
+            Box::pin(async move {
+                let chunk = Bytes::from(vec![b'x'; 2 * ERROR_BODY_EXCERPT_SIZE]);
+                let body = stream::once(async move { Ok(chunk) }).chain(stream::pending());
+                Ok(http::Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Body::wrap_stream(body))
+                    .unwrap())
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_error_body_excerpt() {
+        // Only the beginning of the body is read.
+        let client = ClientBuilder::new()
+            .timeout(Duration::from_secs(10))
+            .build_with_transport(EndlessErrorTransport);
+        match client.resolve("example.org").await {
+            Err(Error::ServerError(response)) => {
+                assert_eq!("x".repeat(ERROR_BODY_EXCERPT_SIZE), response.body_excerpt());
+            }
+            other => panic!("expected server error, got {:?}", other),
+        }
+
+        // The excerpt is kept for bodies larger than the limit.
+        let transport = MemoryTransport::new().with_response(
+            URL,
+            MemoryResponse::new(StatusCode::BAD_GATEWAY, "y".repeat(2000)),
+        );
+        let client = ClientBuilder::new()
+            .max_body_size(1000)
+            .build_with_transport(transport);
+        match client.resolve("example.org").await {
+            Err(Error::ServerError(response)) => {
+                assert_eq!("y".repeat(ERROR_BODY_EXCERPT_SIZE), response.body_excerpt());
+            }
+            other => panic!("expected server error, got {:?}", other),
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    struct StalledTransport {
+        headers_delay: Duration,
+    }
+
+    impl Transport for StalledTransport {
+        fn send(&self, _request: Request<()>) -> ResponseFuture {
+            let delay = self.headers_delay;
+            Box::pin(async move {
+                tokio::time::sleep(delay).await;
+                let body = Body::wrap_stream(stream::pending());
+                Ok(http::Response::new(body))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_first_byte_timeout() {
+        let transport = StalledTransport {
+            headers_delay: Duration::from_secs(10),
+        };
+        let client = ClientBuilder::new()
+            .first_byte_timeout(Duration::from_millis(50))
+            .build_with_transport(transport);
+        match client.resolve("example.org").await {
+            Err(Error::Timeout { phase, url }) => {
+                assert_eq!(TimeoutPhase::FirstByte, phase);
+                assert_eq!(URL, url.to_string());
+            }
+            other => panic!("expected timeout, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_total_timeout() {
+        let transport = StalledTransport {
+            headers_delay: Duration::from_millis(0),
+        };
+        let client = ClientBuilder::new()
+            .first_byte_timeout(Duration::from_secs(10))
+            .timeout(Duration::from_millis(50))
+            .build_with_transport(transport);
+        match client.resolve("example.org").await {
+            Err(Error::Timeout { phase, .. }) => assert_eq!(TimeoutPhase::Total, phase),
+            other => panic!("expected timeout, got {:?}", other),
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    struct EndlessTransport;
+
+    impl Transport for EndlessTransport {
+        fn send(&self, _request: Request<()>) -> ResponseFuture {
+            Box::pin(async move {
+                let chunk = Bytes::from_static(&[b'#'; 1024]);
+                let body = Body::wrap_stream(stream::repeat(chunk).map(Ok));
+                Ok(http::Response::new(body))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_body_too_large() {
+        let client = StellarTomlClient::with_transport(EndlessTransport);
+        match client.resolve("example.org").await {
+            Err(Error::ResponseTooLarge { limit, url }) => {
+                assert_eq!(STELLAR_TOML_MAX_SIZE, limit);
+                assert_eq!(URL, url.to_string());
+            }
+            other => panic!("expected response too large, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_content_length_too_large() {
+        let response =
+            MemoryResponse::ok("VERSION = \"2.0.0\"").with_header("content-length", "1024");
+        let transport = MemoryTransport::new().with_response(URL, response);
+        let client = ClientBuilder::new()
+            .max_body_size(512)
+            .build_with_transport(transport);
+        let result = client.resolve("example.org").await;
+        assert!(matches!(
+            result,
+            Err(Error::ResponseTooLarge { limit: 512, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_body_within_limit() {
+        let body = "VERSION = \"2.0.0\"";
+        let transport = MemoryTransport::new().with_response(URL, MemoryResponse::ok(body));
+        let client = ClientBuilder::new()
+            .max_body_size(body.len())
+            .build_with_transport(transport);
+        assert!(client.resolve("example.org").await.is_ok());
+    }
+
+    fn redirect_transport() -> MemoryTransport {
+        let redirect = |location: &str| {
+            MemoryResponse::new(StatusCode::MOVED_PERMANENTLY, "").with_header("location", location)
+        };
+        MemoryTransport::new()
+            .with_response(
+                URL,
+                redirect("https://www.example.org/.well-known/stellar.toml"),
+            )
+            .with_response(
+                "https://www.example.org/.well-known/stellar.toml",
+                redirect("/stellar.toml"),
+            )
+            .with_response(
+                "https://www.example.org/stellar.toml",
+                MemoryResponse::ok("VERSION = \"2.0.0\""),
+            )
+    }
+
+    #[tokio::test]
+    async fn test_resolve_follows_redirects() {
+        let client = ClientBuilder::new()
+            .redirect_policy(RedirectPolicy::Limited(2))
+            .build_with_transport(redirect_transport());
+        let fetched = client.resolve_with_metadata("example.org").await.unwrap();
+        assert_eq!(Some("2.0.0".to_string()), fetched.stellar_toml.version);
+        assert_eq!("https://www.example.org/stellar.toml", fetched.url);
+        let redirects: Vec<_> = fetched.redirects.iter().map(Uri::to_string).collect();
+        assert_eq!(
+            vec![URL, "https://www.example.org/.well-known/stellar.toml"],
+            redirects
+        );
+    }
+
+    /// Counts the response bodies read to the end.
+    #[derive(Debug, Clone)]
+    struct DrainedTransport {
+        inner: MemoryTransport,
+        drained: Arc<Mutex<usize>>,
+    }
+
+    impl Transport for DrainedTransport {
+        fn send(&self, request: Request<()>) -> ResponseFuture {
+            let sending = self.inner.send(request);
+            let drained = self.drained.clone();
+            Box::pin(async move {
+                let response = sending.await?;
+                Ok(response.map(|body| {
+                    let end = stream::once(async move {
+                        *drained.lock().unwrap() += 1;
+                        Ok(Bytes::new())
+                    });
+                    Body::wrap_stream(body.chain(end))
+                }))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_drains_redirect_bodies() {
+        let transport = DrainedTransport {
+            inner: redirect_transport(),
+            drained: Arc::default(),
+        };
+        let client = ClientBuilder::new()
+            .redirect_policy(RedirectPolicy::Limited(2))
+            .build_with_transport(transport.clone());
+        client.resolve("example.org").await.unwrap();
+        // Both redirects and the final response.
+        assert_eq!(3, *transport.drained.lock().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_with_metadata() {
+        let body = "VERSION = \"2.0.0\"";
+        let response = MemoryResponse::ok(body).with_header("etag", "\"v1\"");
+        let transport = MemoryTransport::new().with_response(URL, response);
+        let client = StellarTomlClient::with_transport(transport);
+        let before = SystemTime::now();
+        let fetched = client.resolve_with_metadata("example.org").await.unwrap();
+        assert_eq!(StatusCode::OK, fetched.status);
+        assert_eq!("\"v1\"", fetched.headers["etag"]);
+        assert_eq!(body.as_bytes(), &fetched.body[..]);
+        let sha256: [u8; 32] = Sha256::digest(body.as_bytes()).into();
+        assert_eq!(sha256, fetched.body_sha256);
+        assert!(fetched.fetched_at >= before);
+        assert!(fetched.fetched_at <= SystemTime::now());
+        assert!(fetched.elapsed < Duration::from_secs(10));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_compliance_warnings() {
+        let compliant = MemoryResponse::ok("VERSION = \"2.0.0\"")
+            .with_header("access-control-allow-origin", "*")
+            .with_header("content-type", "text/plain; charset=utf-8");
+        let transport = MemoryTransport::new().with_response(URL, compliant);
+        let client = ClientBuilder::new()
+            .strict(true)
+            .build_with_transport(transport);
+        let fetched = client.resolve_with_metadata("example.org").await.unwrap();
+        assert!(fetched.warnings.is_empty());
+
+        let non_compliant =
+            MemoryResponse::ok("VERSION = \"2.0.0\"").with_header("content-type", "text/html");
+        let transport = MemoryTransport::new().with_response(URL, non_compliant);
+        let client = StellarTomlClient::with_transport(transport.clone());
+        let fetched = client.resolve_with_metadata("example.org").await.unwrap();
+        let expected = vec![
+            ComplianceWarning::MissingCors,
+            ComplianceWarning::UnexpectedContentType {
+                value: "text/html".to_string(),
+            },
+        ];
+        assert_eq!(expected, fetched.warnings);
+
+        let client = ClientBuilder::new()
+            .strict(true)
+            .build_with_transport(transport);
+        match client.resolve("example.org").await {
+            Err(Error::NonCompliant { warnings, url }) => {
+                assert_eq!(expected, warnings);
+                assert_eq!(URL, url);
+            }
+            other => panic!("expected non compliant error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_redirect_policy() {
+        let client = StellarTomlClient::with_transport(redirect_transport());
+        let result = client.resolve("example.org").await;
+        assert!(matches!(
+            result,
+            Err(Error::TooManyRedirects { limit: 0, .. })
+        ));
+
+        let client = ClientBuilder::new()
+            .redirect_policy(RedirectPolicy::Limited(1))
+            .build_with_transport(redirect_transport());
+        let result = client.resolve("example.org").await;
+        assert!(matches!(
+            result,
+            Err(Error::TooManyRedirects { limit: 1, .. })
+        ));
+
+        let client = ClientBuilder::new()
+            .redirect_policy(RedirectPolicy::SameHost(2))
+            .build_with_transport(redirect_transport());
+        let result = client.resolve("example.org").await;
+        assert!(matches!(result, Err(Error::CrossHostRedirect { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_retries_transient_errors() {
+        let transport = MemoryTransport::new();
+        transport.push(URL, MemoryResponse::new(StatusCode::BAD_GATEWAY, ""));
+        transport.push(
+            URL,
+            MemoryResponse::new(StatusCode::SERVICE_UNAVAILABLE, "")
+                .with_header("retry-after", "0"),
+        );
+        transport.push(URL, MemoryResponse::ok("VERSION = \"2.0.0\""));
+        let policy = RetryPolicy::new(3).initial_backoff(Duration::from_millis(1));
+        let client = ClientBuilder::new()
+            .retry_policy(policy)
+            .build_with_transport(transport.clone());
+        let stellar = client.resolve("example.org").await.unwrap();
+        assert_eq!(Some("2.0.0".to_string()), stellar.version);
+        assert_eq!(3, transport.requests().len());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_reports_attempts() {
+        let transport = MemoryTransport::new().with_response(
+            URL,
+            MemoryResponse::new(StatusCode::SERVICE_UNAVAILABLE, ""),
+        );
+        let policy = RetryPolicy::new(2).initial_backoff(Duration::from_millis(1));
+        let client = ClientBuilder::new()
+            .retry_policy(policy)
+            .build_with_transport(transport.clone());
+        match client.resolve("example.org").await {
+            Err(Error::RetryFailed { attempts, error }) => {
+                assert_eq!(2, attempts);
+                assert!(matches!(*error, Error::ServerError(_)));
+            }
+            other => panic!("expected retry failed, got {:?}", other),
+        }
+        assert_eq!(2, transport.requests().len());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_does_not_retry_permanent_errors() {
+        let transport = MemoryTransport::new();
+        let client = ClientBuilder::new()
+            .retry_policy(RetryPolicy::new(3))
+            .build_with_transport(transport.clone());
+        let result = client.resolve("example.org").await;
+        assert!(matches!(result, Err(Error::ClientError(_))));
+        assert_eq!(1, transport.requests().len());
+    }
+
+    fn cached_client(transport: &MemoryTransport) -> StellarTomlClient<MemoryTransport> {
+        ClientBuilder::new()
+            .cache(CacheConfig::new())
+            .build_with_transport(transport.clone())
+    }
+
+    #[tokio::test]
+    async fn test_resolve_uses_cache() {
+        let transport = MemoryTransport::new().with_response(
+            URL,
+            MemoryResponse::ok("VERSION = \"2.0.0\"").with_header("cache-control", "max-age=60"),
+        );
+        let client = cached_client(&transport);
+        client.resolve("example.org").await.unwrap();
+        let stellar = client.clone().resolve("example.org").await.unwrap();
+        assert_eq!(Some("2.0.0".to_string()), stellar.version);
+        assert_eq!(1, transport.requests().len());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_cache_no_store() {
+        let transport = MemoryTransport::new().with_response(
+            URL,
+            MemoryResponse::ok("VERSION = \"2.0.0\"").with_header("cache-control", "no-store"),
+        );
+        let client = cached_client(&transport);
+        client.resolve("example.org").await.unwrap();
+        client.resolve("example.org").await.unwrap();
+        assert_eq!(2, transport.requests().len());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_cache_revalidates() {
+        let transport = MemoryTransport::new();
+        transport.push(
+            URL,
+            MemoryResponse::ok("VERSION = \"2.0.0\"")
+                .with_header("cache-control", "max-age=0")
+                .with_header("etag", "\"v1\"")
+                .with_header("last-modified", "Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        transport.push(
+            URL,
+            MemoryResponse::new(StatusCode::NOT_MODIFIED, "")
+                .with_header("cache-control", "max-age=60"),
+        );
+        let client = cached_client(&transport);
+        client.resolve("example.org").await.unwrap();
+        let stellar = client.resolve("example.org").await.unwrap();
+        assert_eq!(Some("2.0.0".to_string()), stellar.version);
+        client.resolve("example.org").await.unwrap();
+
+        let requests = transport.requests();
+        assert_eq!(2, requests.len());
+        assert!(requests[0].headers().get("if-none-match").is_none());
+        assert_eq!("\"v1\"", requests[1].headers()["if-none-match"]);
+        assert_eq!(
+            "Wed, 21 Oct 2015 07:28:00 GMT",
+            requests[1].headers()["if-modified-since"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_offline_from_disk() {
+        let directory = std::env::temp_dir().join(format!("stellar-toml-{}", fastrand::u64(..)));
+        let config = CacheConfig::new().directory(&directory);
+        let transport = MemoryTransport::new().with_response(
+            URL,
+            MemoryResponse::ok("VERSION = \"2.0.0\"").with_header("cache-control", "max-age=0"),
+        );
+        let client = ClientBuilder::new()
+            .cache(config.clone())
+            .build_with_transport(transport);
+        client.resolve("example.org").await.unwrap();
+
+        let transport = MemoryTransport::new();
+        let client = ClientBuilder::new()
+            .cache(config)
+            .offline(true)
+            .build_with_transport(transport.clone());
+        let stellar = client.resolve("example.org").await.unwrap();
+        assert_eq!(Some("2.0.0".to_string()), stellar.version);
+        let result = client.resolve("other.example.org").await;
+        assert!(matches!(result, Err(Error::NotCached { .. })));
+        assert!(transport.requests().is_empty());
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[derive(Debug, Clone)]
+    struct SlowTransport {
+        inner: MemoryTransport,
+        delay: Duration,
+    }
+
+    impl Transport for SlowTransport {
+        fn send(&self, request: Request<()>) -> ResponseFuture {
+            let response = self.inner.send(request);
+            let delay = self.delay;
+            Box::pin(async move {
+                tokio::time::sleep(delay).await;
+                response.await
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_notifies_observer() {
+        #[derive(Clone, Default)]
+        struct Outcomes(Arc<Mutex<Vec<String>>>);
+
+        impl ResolverObserver for Outcomes {
+            fn on_lookup(&self, outcome: &LookupOutcome<'_>) {
+                let bytes = outcome.result.ok().map(|fetched| fetched.body.len());
+                let line = format!("{:?} {:?}", outcome.cache, bytes);
+                self.0.lock().unwrap().push(line);
+            }
+        }
+
+        let transport =
+            MemoryTransport::new().with_response(URL, MemoryResponse::ok("VERSION = \"2.0.0\""));
+        let outcomes = Outcomes::default();
+        let client = ClientBuilder::new()
+            .cache(CacheConfig::new())
+            .observer(outcomes.clone())
+            .build_with_transport(transport);
+        client.resolve("example.org").await.unwrap();
+        client.resolve("example.org").await.unwrap();
+        client.resolve("missing.example.org").await.unwrap_err();
+        assert_eq!(
+            vec!["Miss Some(17)", "Hit Some(17)", "Miss None"],
+            *outcomes.0.lock().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_coalesces_concurrent_lookups() {
+        let inner = MemoryTransport::new()
+            .with_response(URL, MemoryResponse::ok("VERSION = \"2.0.0\""))
+            .with_response(
+                "https://broken.example.org/.well-known/stellar.toml",
+                MemoryResponse::new(StatusCode::SERVICE_UNAVAILABLE, ""),
+            );
+        let client = StellarTomlClient::with_transport(SlowTransport {
+            inner: inner.clone(),
+            delay: Duration::from_millis(50),
+        });
+
+        let lookups = (0..10).map(|_| client.resolve("example.org"));
+        for result in futures_util::future::join_all(lookups).await {
+            assert_eq!(Some("2.0.0".to_string()), result.unwrap().version);
+        }
+        assert_eq!(1, inner.requests().len());
+
+        let lookups = (0..10).map(|_| client.resolve("broken.example.org"));
+        for result in futures_util::future::join_all(lookups).await {
+            assert!(matches!(result, Err(Error::ServerError(_))));
+        }
+        assert_eq!(2, inner.requests().len());
+
+        client.resolve("example.org").await.unwrap();
+        assert_eq!(3, inner.requests().len());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_after_cancelled_lookup() {
+        let inner =
+            MemoryTransport::new().with_response(URL, MemoryResponse::ok("VERSION = \"2.0.0\""));
+        let client = ClientBuilder::new()
+            .timeout(Duration::from_millis(150))
+            .build_with_transport(SlowTransport {
+                inner: inner.clone(),
+                delay: Duration::from_millis(100),
+            });
+
+        let cancelled =
+            tokio::time::timeout(Duration::from_millis(20), client.resolve("example.org")).await;
+        assert!(cancelled.is_err());
+        tokio::time::sleep(Duration::from_millis(150)).await;
+
+        let stellar_toml = client.resolve("example.org").await.unwrap();
+        assert_eq!(Some("2.0.0".to_string()), stellar_toml.version);
+        assert_eq!(2, inner.requests().len());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_rate_limited_per_host() {
+        let transport = MemoryTransport::new()
+            .with_response(URL, MemoryResponse::ok("VERSION = \"2.0.0\""))
+            .with_response(
+                "https://other.example.org/.well-known/stellar.toml",
+                MemoryResponse::ok("VERSION = \"2.0.0\""),
+            );
+        let client = ClientBuilder::new()
+            .rate_limit(RateLimit::new(20, Duration::from_secs(1)).burst(1))
+            .build_with_transport(transport.clone());
+
+        let start = std::time::Instant::now();
+        client.resolve("example.org").await.unwrap();
+        client.resolve("other.example.org").await.unwrap();
+        assert!(start.elapsed() < Duration::from_millis(40));
+        client.resolve("example.org").await.unwrap();
+        client.resolve("example.org").await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(90));
+        assert_eq!(4, transport.requests().len());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_invalid_toml() {
+        let transport = MemoryTransport::new().with_response(URL, MemoryResponse::ok("VERSION ="));
+        let client = StellarTomlClient::with_transport(transport);
+        let err = client.resolve("example.org").await.unwrap_err();
+        assert!(matches!(err, Error::TomlParseError { .. }));
+        assert_eq!(format!("invalid stellar.toml at {}", URL), err.to_string());
+        let source = std::error::Error::source(&err).unwrap();
+        assert!(source.is::<toml::de::Error>());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_pinned_public_key() {
+        let certificate = PeerCertificate {
+            fingerprint: Fingerprint::of(b"certificate"),
+            public_key: Fingerprint::of(b"public key"),
+        };
+        let transport = MemoryTransport::new().with_response(
+            URL,
+            MemoryResponse::ok("VERSION = \"2.0.0\"")
+                .with_peer_certificates(vec![certificate.clone()]),
+        );
+        let client = ClientBuilder::new()
+            .pin_public_key("EXAMPLE.org", Fingerprint::of(b"backup key"))
+            .pin_public_key("example.org", Fingerprint::of(b"public key"))
+            .build_with_transport(transport.clone());
+        let fetched = client.resolve_with_metadata("example.org").await.unwrap();
+        assert_eq!(vec![certificate], fetched.peer_certificates);
+
+        let client = ClientBuilder::new()
+            .pin_public_key("example.org", Fingerprint::of(b"other key"))
+            .build_with_transport(transport);
+        match client.resolve("example.org").await {
+            Err(Error::CertificatePinMismatch { url }) => assert_eq!(URL, url.to_string()),
+            other => panic!("expected pin mismatch, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_pinned_without_certificates() {
+        let transport =
+            MemoryTransport::new().with_response(URL, MemoryResponse::ok("VERSION = \"2.0.0\""));
+        let client = ClientBuilder::new()
+            .pin_public_key("example.org", Fingerprint::of(b"public key"))
+            .build_with_transport(transport);
+        let result = client.resolve("example.org").await;
+        assert!(matches!(result, Err(Error::CertificatePinMismatch { .. })));
+    }
+}
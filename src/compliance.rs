@@ -0,0 +1,149 @@
+use http::header::{ACCESS_CONTROL_ALLOW_ORIGIN, CONTENT_TYPE};
+use http::HeaderMap;
+use std::fmt;
+
+/// A SEP-1 requirement that the response serving a `stellar.toml`
+/// file does not meet.
+///
+/// SEP-1 requires the file to be served with
+/// `Access-Control-Allow-Origin: *`, so that browser applications can
+/// read it, and recommends the `text/plain` content type. These
+/// problems don't prevent resolving the file from a server, they are
+/// reported in `FetchedStellarToml::warnings`, or as errors if the
+/// client is built with `ClientBuilder::strict`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ComplianceWarning {
+    /// The response has no `Access-Control-Allow-Origin` header.
+    MissingCors,
+    /// The `Access-Control-Allow-Origin` header is not `*`.
+    InvalidCors {
+        /// The value of the header.
+        value: String,
+    },
+    /// The response has no `Content-Type` header.
+    MissingContentType,
+    /// The `Content-Type` header is neither `text/plain` nor
+    /// `application/toml`.
+    UnexpectedContentType {
+        /// The value of the header.
+        value: String,
+    },
+}
+
+impl fmt::Display for ComplianceWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ComplianceWarning::MissingCors => {
+                f.write_str("missing Access-Control-Allow-Origin header")
+            }
+            ComplianceWarning::InvalidCors { value } => {
+                write!(
+                    f,
+                    "Access-Control-Allow-Origin is {:?}, expected \"*\"",
+                    value
+                )
+            }
+            ComplianceWarning::MissingContentType => f.write_str("missing Content-Type header"),
+            ComplianceWarning::UnexpectedContentType { value } => {
+                write!(
+                    f,
+                    "Content-Type is {:?}, expected \"text/plain\" or \"application/toml\"",
+                    value
+                )
+            }
+        }
+    }
+}
+
+/// Returns the SEP-1 requirements not met by a response with `headers`.
+pub(crate) fn check(headers: &HeaderMap) -> Vec<ComplianceWarning> {
+    let mut warnings = Vec::new();
+    match headers.get(ACCESS_CONTROL_ALLOW_ORIGIN) {
+        None => warnings.push(ComplianceWarning::MissingCors),
+        Some(value) if value.to_str().ok().map(str::trim) == Some("*") => {}
+        Some(value) => warnings.push(ComplianceWarning::InvalidCors {
+            value: String::from_utf8_lossy(value.as_bytes()).into_owned(),
+        }),
+    }
+    match headers.get(CONTENT_TYPE) {
+        None => warnings.push(ComplianceWarning::MissingContentType),
+        Some(value) if is_toml_content_type(value.as_bytes()) => {}
+        Some(value) => warnings.push(ComplianceWarning::UnexpectedContentType {
+            value: String::from_utf8_lossy(value.as_bytes()).into_owned(),
+        }),
+    }
+    warnings
+}
+
+/// Returns `true` if `value` is `text/plain` or `application/toml`,
+/// with any parameters such as the charset.
+fn is_toml_content_type(value: &[u8]) -> bool {
+    let value = String::from_utf8_lossy(value);
+    let media_type = value.split(';').next().unwrap_or_default().trim();
+    media_type.eq_ignore_ascii_case("text/plain")
+        || media_type.eq_ignore_ascii_case("application/toml")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header::HeaderName;
+
+    fn headers(pairs: &[(&str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(
+                HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                value.parse().unwrap(),
+            );
+        }
+        headers
+    }
+
+    #[test]
+    fn test_check_compliant_headers() {
+        let compliant = headers(&[
+            ("access-control-allow-origin", "*"),
+            ("content-type", "text/plain; charset=utf-8"),
+        ]);
+        assert!(check(&compliant).is_empty());
+        let compliant = headers(&[
+            ("access-control-allow-origin", "*"),
+            ("content-type", "Application/TOML"),
+        ]);
+        assert!(check(&compliant).is_empty());
+    }
+
+    #[test]
+    fn test_check_non_compliant_headers() {
+        assert_eq!(
+            vec![
+                ComplianceWarning::MissingCors,
+                ComplianceWarning::MissingContentType
+            ],
+            check(&HeaderMap::new())
+        );
+        let non_compliant = headers(&[
+            ("access-control-allow-origin", "https://example.org"),
+            ("content-type", "text/html"),
+        ]);
+        assert_eq!(
+            vec![
+                ComplianceWarning::InvalidCors {
+                    value: "https://example.org".to_string()
+                },
+                ComplianceWarning::UnexpectedContentType {
+                    value: "text/html".to_string()
+                },
+            ],
+            check(&non_compliant)
+        );
+        let warning = ComplianceWarning::UnexpectedContentType {
+            value: "text/html".to_string(),
+        };
+        assert_eq!(
+            "Content-Type is \"text/html\", expected \"text/plain\" or \"application/toml\"",
+            warning.to_string()
+        );
+    }
+}
@@ -0,0 +1,300 @@
+use http::header::{HeaderValue, CONTENT_ENCODING};
+use http::HeaderMap;
+use std::io::{self, Write};
+
+/// Value of the `Accept-Encoding` header, listing the encodings
+/// enabled by the crate features.
+pub(crate) fn accept_encoding() -> Option<HeaderValue> {
+    let encodings: Vec<&str> = vec![
+        #[cfg(feature = "gzip")]
+        "gzip",
+        #[cfg(feature = "deflate")]
+        "deflate",
+        #[cfg(feature = "brotli")]
+        "br",
+    ];
+    if encodings.is_empty() {
+        return None;
+    }
+    HeaderValue::from_str(&encodings.join(", ")).ok()
+}
+
+/// Error while decoding a response body.
+#[derive(Debug)]
+pub(crate) enum DecodeError {
+    /// The decoded body exceeds the limit.
+    TooLarge,
+    /// The body is not valid for its encoding, or the encoding is not
+    /// supported.
+    Invalid(String),
+}
+
+/// Decodes a response body, as it is received, according to its
+/// `Content-Encoding`.
+///
+/// The size limit is enforced on the decoded body, so that a small
+/// compressed body cannot expand to an arbitrary size.
+pub(crate) enum Decoder {
+    Identity(LimitedWriter),
+    #[cfg(feature = "gzip")]
+    Gzip(flate2::write::GzDecoder<LimitedWriter>),
+    #[cfg(feature = "deflate")]
+    Deflate(flate2::write::ZlibDecoder<LimitedWriter>),
+    #[cfg(feature = "brotli")]
+    Brotli(Box<brotli_decompressor::DecompressorWriter<LimitedWriter>>),
+}
+
+/// Buffer that keeps the first `limit` bytes written to it, and
+/// refuses to grow further.
+pub(crate) struct LimitedWriter {
+    buffer: Vec<u8>,
+    limit: usize,
+}
+
+impl Decoder {
+    /// Creates a decoder for a response with `headers`, whose decoded
+    /// body must not exceed `limit` bytes.
+    pub(crate) fn new(headers: &HeaderMap, limit: usize) -> Result<Decoder, DecodeError> {
+        let writer = LimitedWriter {
+            buffer: Vec::new(),
+            limit,
+        };
+        let encoding = match headers.get(CONTENT_ENCODING) {
+            None => return Ok(Decoder::Identity(writer)),
+            Some(encoding) => encoding.to_str().unwrap_or_default().trim(),
+        };
+        match encoding.to_ascii_lowercase().as_str() {
+            "" | "identity" => Ok(Decoder::Identity(writer)),
+            #[cfg(feature = "gzip")]
+            "gzip" | "x-gzip" => Ok(Decoder::Gzip(flate2::write::GzDecoder::new(writer))),
+            #[cfg(feature = "deflate")]
+            "deflate" => Ok(Decoder::Deflate(flate2::write::ZlibDecoder::new(writer))),
+            #[cfg(feature = "brotli")]
+            "br" => Ok(Decoder::Brotli(Box::new(
+                brotli_decompressor::DecompressorWriter::new(writer, 4096),
+            ))),
+            _ => Err(DecodeError::Invalid(format!(
+                "unsupported content encoding {}",
+                encoding
+            ))),
+        }
+    }
+
+    /// Returns `true` if the body is not encoded.
+    pub(crate) fn is_identity(&self) -> bool {
+        matches!(self, Decoder::Identity(_))
+    }
+
+    /// Decodes the next `chunk` of the body.
+    pub(crate) fn write(&mut self, chunk: &[u8]) -> Result<(), DecodeError> {
+        let result = match self {
+            Decoder::Identity(writer) => writer.write_all(chunk),
+            #[cfg(feature = "gzip")]
+            Decoder::Gzip(decoder) => decoder.write_all(chunk),
+            #[cfg(feature = "deflate")]
+            Decoder::Deflate(decoder) => decoder.write_all(chunk),
+            #[cfg(feature = "brotli")]
+            Decoder::Brotli(decoder) => decoder.write_all(chunk),
+        };
+        result.map_err(decode_error)
+    }
+
+    /// Checks that the body is complete and returns it decoded.
+    pub(crate) fn finish(self) -> Result<Vec<u8>, DecodeError> {
+        let writer = match self {
+            Decoder::Identity(writer) => Ok(writer),
+            #[cfg(feature = "gzip")]
+            Decoder::Gzip(decoder) => decoder.finish(),
+            #[cfg(feature = "deflate")]
+            Decoder::Deflate(decoder) => decoder.finish(),
+            #[cfg(feature = "brotli")]
+            Decoder::Brotli(mut decoder) => match decoder.close() {
+                Ok(()) => decoder.into_inner().map_err(|_| truncated()),
+                Err(err) => Err(err),
+            },
+        };
+        Ok(writer.map_err(decode_error)?.buffer)
+    }
+
+    /// Returns the part of the body decoded so far, at most `limit`
+    /// bytes, without checking that the body is complete.
+    pub(crate) fn into_prefix(self) -> Vec<u8> {
+        match self {
+            Decoder::Identity(writer) => writer.buffer,
+            #[cfg(feature = "gzip")]
+            Decoder::Gzip(mut decoder) => {
+                let _ = decoder.flush();
+                std::mem::take(&mut decoder.get_mut().buffer)
+            }
+            #[cfg(feature = "deflate")]
+            Decoder::Deflate(mut decoder) => {
+                let _ = decoder.flush();
+                std::mem::take(&mut decoder.get_mut().buffer)
+            }
+            #[cfg(feature = "brotli")]
+            Decoder::Brotli(mut decoder) => {
+                let _ = decoder.flush();
+                std::mem::take(&mut decoder.get_mut().buffer)
+            }
+        }
+    }
+}
+
+impl Write for LimitedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.limit - self.buffer.len());
+        if len == 0 && !buf.is_empty() {
+            return Err(io::Error::other(TooLarge));
+        }
+        self.buffer.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Marker error returned by `LimitedWriter` when full.
+#[derive(Debug)]
+struct TooLarge;
+
+impl std::fmt::Display for TooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("body too large")
+    }
+}
+
+impl std::error::Error for TooLarge {}
+
+#[cfg(feature = "brotli")]
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "truncated body")
+}
+
+fn decode_error(err: io::Error) -> DecodeError {
+    let too_large = err
+        .get_ref()
+        .map(|inner| inner.is::<TooLarge>())
+        .unwrap_or(false);
+    if too_large {
+        DecodeError::TooLarge
+    } else {
+        DecodeError::Invalid(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STELLAR_TOML: &[u8] = b"VERSION = \"2.0.0\"\n";
+
+    fn headers(encoding: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_ENCODING, encoding.parse().unwrap());
+        headers
+    }
+
+    fn decode(encoding: &str, body: &[u8], limit: usize) -> Result<Vec<u8>, DecodeError> {
+        let mut decoder = Decoder::new(&headers(encoding), limit)?;
+        // Feed the body in small chunks, as received from the network.
+        for chunk in body.chunks(3) {
+            decoder.write(chunk)?;
+        }
+        decoder.finish()
+    }
+
+    #[test]
+    fn test_decode_identity() {
+        assert_eq!(
+            STELLAR_TOML,
+            &decode("identity", STELLAR_TOML, 100).unwrap()[..]
+        );
+        assert!(matches!(
+            decode("identity", STELLAR_TOML, 10),
+            Err(DecodeError::TooLarge)
+        ));
+        assert!(matches!(
+            decode("compress", STELLAR_TOML, 100),
+            Err(DecodeError::Invalid(_))
+        ));
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_decode_gzip() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(STELLAR_TOML).unwrap();
+        let body = encoder.finish().unwrap();
+        assert_eq!(STELLAR_TOML, &decode("gzip", &body, 100).unwrap()[..]);
+        assert!(matches!(
+            decode("gzip", &body[..body.len() - 4], 100),
+            Err(DecodeError::Invalid(_))
+        ));
+    }
+
+    #[cfg(feature = "deflate")]
+    #[test]
+    fn test_decode_deflate() {
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(STELLAR_TOML).unwrap();
+        let body = encoder.finish().unwrap();
+        assert_eq!(STELLAR_TOML, &decode("deflate", &body, 100).unwrap()[..]);
+    }
+
+    #[cfg(feature = "brotli")]
+    #[test]
+    fn test_decode_brotli() {
+        // An uncompressed meta-block, followed by an empty last one.
+        let mut body = vec![0x10, 0x01, 0x10];
+        body.extend_from_slice(STELLAR_TOML);
+        body.push(0x03);
+        assert_eq!(STELLAR_TOML, &decode("br", &body, 100).unwrap()[..]);
+        assert!(matches!(
+            decode("br", &body, 10),
+            Err(DecodeError::TooLarge)
+        ));
+        assert!(matches!(
+            decode("br", &body[..body.len() - 1], 100),
+            Err(DecodeError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_decode_prefix() {
+        let mut decoder = Decoder::new(&headers("identity"), 10).unwrap();
+        decoder.write(&STELLAR_TOML[..4]).unwrap();
+        assert!(matches!(
+            decoder.write(&STELLAR_TOML[4..]),
+            Err(DecodeError::TooLarge)
+        ));
+        assert_eq!(&STELLAR_TOML[..10], &decoder.into_prefix()[..]);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_decode_gzip_prefix() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(&[b'#'; 1024]).unwrap();
+        let body = encoder.finish().unwrap();
+        let mut decoder = Decoder::new(&headers("gzip"), 100).unwrap();
+        // The decoder buffers its output, and may only hit the limit
+        // when flushed.
+        let _ = decoder.write(&body);
+        assert_eq!(vec![b'#'; 100], decoder.into_prefix());
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_decode_limits_decompressed_size() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(&[b'#'; 1024 * 1024]).unwrap();
+        let body = encoder.finish().unwrap();
+        assert!(body.len() < 10 * 1024);
+        assert!(matches!(
+            decode("gzip", &body, 100 * 1024),
+            Err(DecodeError::TooLarge)
+        ));
+    }
+}
@@ -0,0 +1,328 @@
+use crate::Error;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+/// Addresses that are not reachable from the public internet, or
+/// that reach services of the local network or cloud provider.
+const BLOCKED_RANGES: &[(IpAddr, u8)] = &[
+    // "This" network.
+    (IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 8),
+    // Private networks.
+    (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 0)), 8),
+    (IpAddr::V4(Ipv4Addr::new(172, 16, 0, 0)), 12),
+    (IpAddr::V4(Ipv4Addr::new(192, 168, 0, 0)), 16),
+    // Carrier-grade NAT, also used by some cloud metadata services.
+    (IpAddr::V4(Ipv4Addr::new(100, 64, 0, 0)), 10),
+    // Loopback.
+    (IpAddr::V4(Ipv4Addr::new(127, 0, 0, 0)), 8),
+    // Link-local, including the 169.254.169.254 metadata service.
+    (IpAddr::V4(Ipv4Addr::new(169, 254, 0, 0)), 16),
+    // IETF protocol assignments.
+    (IpAddr::V4(Ipv4Addr::new(192, 0, 0, 0)), 24),
+    // Benchmarking.
+    (IpAddr::V4(Ipv4Addr::new(198, 18, 0, 0)), 15),
+    // Multicast, reserved and broadcast.
+    (IpAddr::V4(Ipv4Addr::new(224, 0, 0, 0)), 4),
+    (IpAddr::V4(Ipv4Addr::new(240, 0, 0, 0)), 4),
+    // Unspecified and loopback.
+    (IpAddr::V6(Ipv6Addr::UNSPECIFIED), 128),
+    (IpAddr::V6(Ipv6Addr::LOCALHOST), 128),
+    // Unique local, including the fd00:ec2::254 metadata service.
+    (IpAddr::V6(Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 0)), 7),
+    // Link-local.
+    (IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0)), 10),
+    // Multicast.
+    (IpAddr::V6(Ipv6Addr::new(0xff00, 0, 0, 0, 0, 0, 0, 0)), 8),
+];
+
+/// A range of ip addresses, in CIDR notation.
+///
+/// ```rust
+/// use stellar_toml::IpRange;
+///
+/// # fn run() -> std::result::Result<(), stellar_toml::Error> {
+/// let range: IpRange = "10.0.0.0/8".parse()?;
+/// assert!(range.contains("10.1.2.3".parse().unwrap()));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpRange {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+/// Filter applied to the addresses the resolver connects to.
+#[derive(Debug, Clone)]
+pub(crate) struct IpFilter {
+    pub(crate) block_private: bool,
+    pub(crate) allowed: Vec<IpRange>,
+}
+
+impl IpRange {
+    /// Creates the range of addresses that share the first
+    /// `prefix_len` bits with `network`.
+    pub fn new(network: IpAddr, prefix_len: u8) -> Result<IpRange, Error> {
+        let max_prefix_len = match network {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        if prefix_len > max_prefix_len {
+            return Err(Error::InvalidIpRange {
+                range: format!("{}/{}", network, prefix_len),
+            });
+        }
+        Ok(IpRange {
+            network,
+            prefix_len,
+        })
+    }
+
+    /// Returns `true` if `address` is in the range.
+    pub fn contains(&self, address: IpAddr) -> bool {
+        match (self.network, canonical(address)) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(self.prefix_len));
+                let mask = mask.unwrap_or(0);
+                u32::from(network) & mask == u32::from(address) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(self.prefix_len));
+                let mask = mask.unwrap_or(0);
+                u128::from(network) & mask == u128::from(address) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl From<IpAddr> for IpRange {
+    fn from(address: IpAddr) -> IpRange {
+        let prefix_len = match address {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        IpRange {
+            network: address,
+            prefix_len,
+        }
+    }
+}
+
+impl FromStr for IpRange {
+    type Err = Error;
+
+    /// Parses a range such as `10.0.0.0/8`, or a single address.
+    fn from_str(s: &str) -> Result<IpRange, Error> {
+        let invalid = || Error::InvalidIpRange {
+            range: s.to_string(),
+        };
+        match s.split_once('/') {
+            None => Ok(IpRange::from(s.parse::<IpAddr>().map_err(|_| invalid())?)),
+            Some((network, prefix_len)) => IpRange::new(
+                network.parse().map_err(|_| invalid())?,
+                prefix_len.parse().map_err(|_| invalid())?,
+            ),
+        }
+    }
+}
+
+impl fmt::Display for IpRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix_len)
+    }
+}
+
+impl IpFilter {
+    /// Returns `true` if the resolver can connect to `address`.
+    pub(crate) fn is_allowed(&self, address: IpAddr) -> bool {
+        !self.block_private
+            || !is_private(address)
+            || self.allowed.iter().any(|range| range.contains(address))
+    }
+}
+
+impl Default for IpFilter {
+    fn default() -> IpFilter {
+        IpFilter {
+            block_private: true,
+            allowed: Vec::new(),
+        }
+    }
+}
+
+/// The resolver refused to connect to an address.
+#[derive(Debug)]
+pub(crate) struct BlockedAddress(pub(crate) IpAddr);
+
+impl fmt::Display for BlockedAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "connecting to {} is not allowed", self.0)
+    }
+}
+
+impl std::error::Error for BlockedAddress {}
+
+/// Returns `true` if `address` is private, loopback, link-local or
+/// otherwise not a public internet address.
+///
+/// IPv6 addresses that embed an IPv4 address are also private if the
+/// IPv4 address is.
+fn is_private(address: IpAddr) -> bool {
+    let is_blocked = |address: IpAddr| {
+        BLOCKED_RANGES.iter().any(|(network, prefix_len)| {
+            let range = IpRange {
+                network: *network,
+                prefix_len: *prefix_len,
+            };
+            range.contains(address)
+        })
+    };
+    match canonical(address) {
+        IpAddr::V6(v6) => match embedded_ipv4(v6) {
+            Some(v4) => is_blocked(IpAddr::V6(v6)) || is_blocked(IpAddr::V4(v4)),
+            None => is_blocked(IpAddr::V6(v6)),
+        },
+        address => is_blocked(address),
+    }
+}
+
+/// Returns the IPv4 address reached through a NAT64 (`64:ff9b::/96`),
+/// 6to4 (`2002::/16`) or IPv4-compatible (`::/96`) address.
+fn embedded_ipv4(address: Ipv6Addr) -> Option<Ipv4Addr> {
+    let ipv4 = |high: u16, low: u16| Ipv4Addr::from((u32::from(high) << 16) | u32::from(low));
+    match address.segments() {
+        [0x64, 0xff9b, 0, 0, 0, 0, high, low] | [0, 0, 0, 0, 0, 0, high, low] => {
+            Some(ipv4(high, low))
+        }
+        [0x2002, high, low, _, _, _, _, _] => Some(ipv4(high, low)),
+        _ => None,
+    }
+}
+
+/// Converts IPv4-mapped IPv6 addresses to IPv4.
+fn canonical(address: IpAddr) -> IpAddr {
+    match address {
+        IpAddr::V6(v6) => match v6.segments() {
+            [0, 0, 0, 0, 0, 0xffff, high, low] => {
+                IpAddr::V4(Ipv4Addr::from((u32::from(high) << 16) | u32::from(low)))
+            }
+            _ => address,
+        },
+        address => address,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::testing::serve_stellar_toml;
+    use crate::StellarTomlClient;
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    #[test]
+    fn test_ip_range_contains() {
+        let range: IpRange = "10.0.0.0/8".parse().unwrap();
+        assert!(range.contains(ip("10.255.0.1")));
+        assert!(range.contains(ip("::ffff:10.0.0.1")));
+        assert!(!range.contains(ip("11.0.0.1")));
+        assert!(!range.contains(ip("::1")));
+        let range: IpRange = "0.0.0.0/0".parse().unwrap();
+        assert!(range.contains(ip("8.8.8.8")));
+        let range: IpRange = "fd00::/8".parse().unwrap();
+        assert!(range.contains(ip("fd00:ec2::254")));
+        assert!(IpRange::from(ip("127.0.0.1")).contains(ip("127.0.0.1")));
+        assert!(!IpRange::from(ip("127.0.0.1")).contains(ip("127.0.0.2")));
+        assert!("10.0.0.0/33".parse::<IpRange>().is_err());
+        assert!("localhost".parse::<IpRange>().is_err());
+    }
+
+    #[test]
+    fn test_ip_filter_blocks_private_addresses() {
+        let filter = IpFilter::default();
+        for address in &[
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.20.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.100.100.200",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fe80::1",
+            "fd00:ec2::254",
+            "::ffff:127.0.0.1",
+            "64:ff9b::10.0.0.1",
+            "64:ff9b::169.254.169.254",
+            "2002:a00:1::1",
+            "2002:7f00:1::",
+            "::10.1.2.3",
+            "::169.254.169.254",
+        ] {
+            assert!(!filter.is_allowed(ip(address)), "{}", address);
+        }
+        for address in &[
+            "8.8.8.8",
+            "172.32.0.1",
+            "2001:4860:4860::8888",
+            "64:ff9b::8.8.8.8",
+            "2002:808:808::1",
+        ] {
+            assert!(filter.is_allowed(ip(address)), "{}", address);
+        }
+    }
+
+    #[test]
+    fn test_ip_filter_allow_list() {
+        let filter = IpFilter {
+            block_private: true,
+            allowed: vec!["127.0.0.0/8".parse().unwrap()],
+        };
+        assert!(filter.is_allowed(ip("127.0.0.1")));
+        assert!(!filter.is_allowed(ip("::1")));
+        let filter = IpFilter {
+            block_private: false,
+            allowed: Vec::new(),
+        };
+        assert!(filter.is_allowed(ip("169.254.169.254")));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_blocks_private_addresses() {
+        let address = serve_stellar_toml().await;
+        let client = StellarTomlClient::new();
+        for host in &[address.to_string(), format!("localhost:{}", address.port())] {
+            let url = format!("http://{}/.well-known/stellar.toml", host)
+                .parse()
+                .unwrap();
+            match client.resolve_url(&url).await {
+                Err(Error::BlockedAddress { address, .. }) => assert!(address.is_loopback()),
+                other => panic!("expected blocked address, got {:?}", other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_allowed_private_addresses() {
+        let address = serve_stellar_toml().await;
+        let url = format!("http://{}/.well-known/stellar.toml", address)
+            .parse()
+            .unwrap();
+        let client = StellarTomlClient::builder()
+            .allow_ip_range("127.0.0.0/8".parse().unwrap())
+            .build();
+        let stellar = client.resolve_url(&url).await.unwrap();
+        assert_eq!(Some("2.0.0".to_string()), stellar.version);
+
+        let client = StellarTomlClient::builder()
+            .block_private_addresses(false)
+            .build();
+        let stellar = client.resolve_url(&url).await.unwrap();
+        assert_eq!(Some("2.0.0".to_string()), stellar.version);
+    }
+}
@@ -8,7 +8,8 @@
 //!
 //! ## Example Usage
 //!
-//! ```rust
+#![cfg_attr(feature = "hyper-transport", doc = "```rust")]
+#![cfg_attr(not(feature = "hyper-transport"), doc = "```ignore")]
 //! use stellar_toml::resolve;
 //!
 //! # async fn run() -> std::result::Result<(), stellar_toml::Error> {
@@ -19,15 +20,112 @@
 //! # Ok(())
 //! # }
 //! ```
+//!
+//! If you need to resolve many `stellar.toml` files, create a
+//! `StellarTomlClient` once and reuse it, so that connections are
+//! pooled between lookups.
+//!
+#![cfg_attr(feature = "hyper-transport", doc = "```rust")]
+#![cfg_attr(not(feature = "hyper-transport"), doc = "```ignore")]
+//! use stellar_toml::StellarTomlClient;
+//!
+//! # async fn run() -> std::result::Result<(), stellar_toml::Error> {
+//! let client = StellarTomlClient::new();
+//! for domain in &["www.stellar.org", "stablecoin.anchorusd.com"] {
+//!     let stellar = client.resolve(domain).await?;
+//!     println!("{}: {:?}", domain, stellar.network_passphrase);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## Features
+//!
+//! - `native-tls` (default): connect with https using the system TLS
+//!   library and root certificates.
+//! - `rustls-tls`: connect with https using `rustls` and the Mozilla
+//!   root certificates. Takes precedence over `native-tls` when both
+//!   are enabled.
+//! - `hyper-transport`: provides the `HyperTransport`, that runs on
+//!   `tokio`. Enabled by the TLS features.
+//! - `gzip`, `deflate` and `brotli` (default): decode responses with
+//!   the corresponding `Content-Encoding`.
+//! - `blocking`: provides a blocking client in the `blocking` module.
+//! - `tracing`: records `tracing` spans and events for each phase of
+//!   a lookup: DNS resolution, connection, TLS handshake, request,
+//!   response body and parsing.
+//! - `metrics`: provides the `MetricsObserver`, that records lookups
+//!   with the `metrics` facade.
+//!
+//! Parsing `stellar.toml` files doesn't depend on any runtime. To
+//! resolve files with another executor, disable the default features
+//! and build the client with your own `Transport` and `Timer`.
 
 #[macro_use]
 extern crate serde_derive;
 use http::uri::Uri;
-use hyper::Client;
-use hyper_tls::HttpsConnector;
+use http::{HeaderMap, StatusCode};
+use std::net::IpAddr;
 use std::result::Result;
+use std::sync::Arc;
 use stellar_base::PublicKey;
 
+#[cfg(all(
+    feature = "hyper-transport",
+    not(any(feature = "native-tls", feature = "rustls-tls"))
+))]
+compile_error!("the `hyper-transport` feature requires the `native-tls` or `rustls-tls` feature");
+
+// Declared first, so that its macros are available to the other modules.
+#[macro_use]
+mod trace;
+mod base64;
+mod batch;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+mod cache;
+mod client;
+mod compliance;
+mod decompress;
+#[cfg(feature = "hyper-transport")]
+mod ip_filter;
+mod observer;
+mod pinning;
+#[cfg(feature = "hyper-transport")]
+mod proxy;
+mod rate_limit;
+mod redirect;
+mod retry;
+mod single_flight;
+mod timer;
+#[cfg(feature = "hyper-transport")]
+mod tls;
+pub mod transport;
+
+pub use batch::{BatchSummary, Resolution};
+pub use cache::CacheConfig;
+pub use client::{ClientBuilder, FetchedStellarToml, StellarTomlClient};
+pub use compliance::ComplianceWarning;
+#[cfg(feature = "hyper-transport")]
+pub use ip_filter::IpRange;
+#[cfg(feature = "metrics")]
+pub use observer::MetricsObserver;
+pub use observer::{CacheStatus, LookupOutcome, ResolverObserver};
+pub use pinning::{Fingerprint, PeerCertificate};
+#[cfg(feature = "hyper-transport")]
+pub use proxy::Proxy;
+pub use rate_limit::RateLimit;
+pub use redirect::RedirectPolicy;
+pub use retry::RetryPolicy;
+#[cfg(feature = "tokio")]
+pub use timer::TokioTimer;
+pub use timer::{SleepFuture, ThreadTimer, Timer};
+#[cfg(feature = "hyper-transport")]
+pub use tls::Certificate;
+#[cfg(feature = "hyper-transport")]
+pub use transport::{DnsResolver, HyperTransport};
+pub use transport::{MemoryResponse, MemoryTransport, Transport};
+
 /// The stellar.toml file is used to provide a common place where the Internet can find information about your organization’s Stellar integration.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StellarToml {
@@ -441,33 +539,36 @@ pub struct Validator {
 /// `stellar.toml` path.
 pub const STELLAR_TOML_PATH: &str = ".well-known/stellar.toml";
 
+/// Maximum size, in bytes, of a `stellar.toml` file as defined by SEP-1.
+pub const STELLAR_TOML_MAX_SIZE: usize = 100 * 1024;
+
+/// Maximum size, in bytes, of the body excerpt of an `ErrorResponse`.
+pub const ERROR_BODY_EXCERPT_SIZE: usize = 512;
+
+/// Default `User-Agent` sent by the client.
+pub const DEFAULT_USER_AGENT: &str = concat!("stellar-toml-rs/", env!("CARGO_PKG_VERSION"));
+
 /// Returns a parsed `stellar.toml` file at `domain`.
 ///
 /// This function will always fetch the `stellar.toml` file using https.
 /// If you need to fetch the file using http, you should build the
 /// uri using `stellar_toml_path_insecure` and then call `resolve_url`.
 /// Note, however, that this is not recommended.
+///
+/// This function uses a new default `StellarTomlClient` on every
+/// call, use a shared client to reuse connections between lookups.
+#[cfg(feature = "hyper-transport")]
 pub async fn resolve(domain: &str) -> Result<StellarToml, Error> {
-    let url = stellar_toml_path(&domain)?;
-    resolve_url(&url).await
+    StellarTomlClient::new().resolve(domain).await
 }
 
-/// Returns a parsed `stellar.toml` file at `domain`.
+/// Returns a parsed `stellar.toml` file at `url`.
+///
+/// This function uses a new default `StellarTomlClient` on every
+/// call, use a shared client to reuse connections between lookups.
+#[cfg(feature = "hyper-transport")]
 pub async fn resolve_url(url: &Uri) -> Result<StellarToml, Error> {
-    let https = HttpsConnector::new();
-    let client = Client::builder().build::<_, hyper::Body>(https);
-    let uri: hyper::Uri = url.to_string().parse()?;
-    let response = client.get(uri).await?;
-
-    if response.status().is_success() {
-        let bytes = hyper::body::to_bytes(response).await?;
-        let result: StellarToml = toml::from_slice(&bytes)?;
-        Ok(result)
-    } else if response.status().is_client_error() {
-        Err(Error::ClientError(response))
-    } else {
-        Err(Error::ServerError(response))
-    }
+    StellarTomlClient::new().resolve_url(url).await
 }
 
 /// Returns the https uri to the `stellar.toml` file at `domain`.
@@ -485,23 +586,359 @@ pub fn stellar_toml_path_insecure(domain: &str) -> Result<Uri, Error> {
 }
 
 /// Crate error type.
-#[derive(thiserror::Error, Debug)]
+///
+/// Errors can be cloned, so that the result of a lookup can be shared
+/// by concurrent callers. Use `Error::status`, `Error::is_not_found`
+/// and `Error::is_retryable` to handle common failures without
+/// matching on every variant.
+///
+#[cfg_attr(feature = "hyper-transport", doc = "```rust")]
+#[cfg_attr(not(feature = "hyper-transport"), doc = "```ignore")]
+/// # async fn run() -> std::result::Result<(), stellar_toml::Error> {
+/// match stellar_toml::resolve("www.stellar.org").await {
+///     Ok(stellar) => println!("{:?}", stellar.version),
+///     Err(err) if err.is_not_found() => println!("no stellar.toml"),
+///     Err(err) => return Err(err),
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(thiserror::Error, Debug, Clone)]
+#[non_exhaustive]
 pub enum Error {
-    /// The client sent a bad request.
-    #[error("client response error")]
-    ClientError(hyper::Response<hyper::Body>),
-    /// Server error response.
-    #[error("server response error")]
-    ServerError(hyper::Response<hyper::Body>),
+    /// The server responded with a 4xx status code.
+    #[error("client error: {0}")]
+    ClientError(Box<ErrorResponse>),
+    /// The server responded with a 5xx status code, or another
+    /// unsuccessful status code.
+    #[error("server error: {0}")]
+    ServerError(Box<ErrorResponse>),
     /// Toml was not a valid `stellar.toml` file.
-    #[error("toml parse error")]
-    TomlParseError(#[from] toml::de::Error),
-    /// Http error.
-    #[error("hyper error")]
-    HyperError(#[from] hyper::Error),
+    #[error("invalid stellar.toml at {url}")]
+    TomlParseError {
+        /// The url of the file.
+        url: Uri,
+        /// The parse error.
+        #[source]
+        source: toml::de::Error,
+    },
     /// Invalid url format.
     #[error("invalid uri")]
-    InvalidUri(#[from] http::uri::InvalidUri),
+    InvalidUri(#[source] Arc<http::uri::InvalidUri>),
+    /// Error returned by the transport while sending the request or
+    /// receiving the response.
+    #[error("transport error requesting {url}: {source}")]
+    TransportError {
+        /// The url of the request.
+        url: Uri,
+        /// The error returned by the transport.
+        #[source]
+        source: Arc<dyn std::error::Error + Send + Sync>,
+    },
+    /// The request timed out.
+    #[error("{phase} timeout for {url}")]
+    Timeout {
+        /// The phase of the request that timed out.
+        phase: TimeoutPhase,
+        /// The url being resolved.
+        url: Uri,
+    },
+    /// The response redirected more times than allowed by the redirect policy.
+    #[error("too many redirects resolving {url}, limit is {limit}")]
+    TooManyRedirects {
+        /// The maximum number of redirects allowed.
+        limit: usize,
+        /// The url being resolved.
+        url: Uri,
+    },
+    /// The response redirected from https to http.
+    #[error("insecure redirect to {url}")]
+    InsecureRedirect {
+        /// The redirect location.
+        url: Uri,
+    },
+    /// The response redirected to a different host, and the redirect
+    /// policy only allows redirects to the same host.
+    #[error("cross host redirect to {url}")]
+    CrossHostRedirect {
+        /// The redirect location.
+        url: Uri,
+    },
+    /// The redirect response has a missing or invalid location.
+    #[error("invalid redirect from {url}")]
+    InvalidRedirect {
+        /// The url that responded with the redirect.
+        url: Uri,
+    },
+    /// The lookup failed after being retried.
+    #[error("lookup failed after {attempts} attempts")]
+    RetryFailed {
+        /// The number of attempts made.
+        attempts: u32,
+        /// The error returned by the last attempt.
+        #[source]
+        error: Box<Error>,
+    },
+    /// The client is offline and the `stellar.toml` file was never cached.
+    #[error("{url} is not cached")]
+    NotCached {
+        /// The url being resolved.
+        url: Uri,
+    },
+    /// The response body is larger than the configured limit.
+    #[error("response from {url} exceeds {limit} bytes")]
+    ResponseTooLarge {
+        /// The maximum size of the body, in bytes.
+        limit: usize,
+        /// The url being resolved.
+        url: Uri,
+    },
+    /// The proxy url is not valid.
+    #[error("invalid proxy: {reason}")]
+    InvalidProxy {
+        /// Why the url is not valid.
+        reason: String,
+    },
+    /// The root certificate is not valid.
+    #[error("invalid certificate: {reason}")]
+    InvalidCertificate {
+        /// Why the certificate is not valid.
+        reason: String,
+    },
+    /// The fingerprint is not a valid SHA-256 fingerprint.
+    #[error("invalid fingerprint {fingerprint}")]
+    InvalidFingerprint {
+        /// The invalid fingerprint.
+        fingerprint: String,
+    },
+    /// None of the certificates sent by the server has a pinned public key.
+    #[error("certificate of {url} does not match the pinned public keys")]
+    CertificatePinMismatch {
+        /// The url that served the certificate.
+        url: Uri,
+    },
+    /// The ip range is not valid.
+    #[error("invalid ip range {range}")]
+    InvalidIpRange {
+        /// The invalid range.
+        range: String,
+    },
+    /// The host resolved to private addresses only, and connecting to
+    /// them is not allowed.
+    #[error("connecting to {address} is not allowed, resolving {url}")]
+    BlockedAddress {
+        /// The first address the host resolved to.
+        address: IpAddr,
+        /// The url being resolved.
+        url: Uri,
+    },
+    /// The proxy failed to establish a tunnel to the host.
+    #[error("proxy error connecting to {url}: {reason}")]
+    Proxy {
+        /// The reason reported by, or about, the proxy.
+        reason: String,
+        /// The url being resolved.
+        url: Uri,
+    },
+    /// The response body could not be decoded according to its
+    /// `Content-Encoding`.
+    #[error("invalid encoded response from {url}: {reason}")]
+    InvalidContentEncoding {
+        /// The reason the body could not be decoded.
+        reason: String,
+        /// The url of the response.
+        url: Uri,
+    },
+    /// The response does not meet the SEP-1 requirements, and the
+    /// client is in strict mode.
+    #[error("{url} does not comply with SEP-1")]
+    NonCompliant {
+        /// The requirements that the response does not meet.
+        warnings: Vec<ComplianceWarning>,
+        /// The url of the response.
+        url: Uri,
+    },
+}
+
+impl Error {
+    /// Returns the status code of the response, if the server
+    /// responded with an unsuccessful status code.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Error::ClientError(response) | Error::ServerError(response) => Some(response.status()),
+            Error::RetryFailed { error, .. } => error.status(),
+            _ => None,
+        }
+    }
+
+    /// Returns the url related to the error, if known.
+    ///
+    /// This is usually the url being resolved, or the redirect
+    /// location for redirect errors.
+    pub fn url(&self) -> Option<&Uri> {
+        match self {
+            Error::ClientError(response) | Error::ServerError(response) => Some(response.url()),
+            Error::TomlParseError { url, .. }
+            | Error::TransportError { url, .. }
+            | Error::Timeout { url, .. }
+            | Error::TooManyRedirects { url, .. }
+            | Error::InsecureRedirect { url }
+            | Error::CrossHostRedirect { url }
+            | Error::InvalidRedirect { url }
+            | Error::NotCached { url }
+            | Error::ResponseTooLarge { url, .. }
+            | Error::CertificatePinMismatch { url }
+            | Error::BlockedAddress { url, .. }
+            | Error::Proxy { url, .. }
+            | Error::InvalidContentEncoding { url, .. }
+            | Error::NonCompliant { url, .. } => Some(url),
+            Error::RetryFailed { error, .. } => error.url(),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the server responded with `404 Not Found` or
+    /// `410 Gone`, meaning that the domain has no `stellar.toml` file.
+    pub fn is_not_found(&self) -> bool {
+        matches!(
+            self.status(),
+            Some(StatusCode::NOT_FOUND) | Some(StatusCode::GONE)
+        )
+    }
+
+    /// Returns `true` if the error is transient, and the lookup may
+    /// succeed if retried later.
+    ///
+    /// These are the errors retried by a `RetryPolicy`: refused, reset
+    /// or interrupted connections, connect and first byte timeouts,
+    /// and the `429`, `502`, `503` and `504` status codes.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::RetryFailed { error, .. } => error.is_retryable(),
+            _ => retry::is_transient(self),
+        }
+    }
+
+    /// Returns a short name of the kind of error, recorded in traces
+    /// and metrics.
+    #[cfg(any(feature = "tracing", feature = "metrics"))]
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            Error::ClientError(_) => "client_error",
+            Error::ServerError(_) => "server_error",
+            Error::TomlParseError { .. } => "toml",
+            Error::InvalidUri(_) => "invalid_uri",
+            Error::TransportError { .. } => "transport",
+            Error::Timeout { .. } => "timeout",
+            Error::TooManyRedirects { .. }
+            | Error::InsecureRedirect { .. }
+            | Error::CrossHostRedirect { .. }
+            | Error::InvalidRedirect { .. } => "redirect",
+            Error::RetryFailed { error, .. } => error.kind(),
+            Error::NotCached { .. } => "not_cached",
+            Error::ResponseTooLarge { .. } => "too_large",
+            Error::InvalidProxy { .. } | Error::Proxy { .. } => "proxy",
+            Error::InvalidCertificate { .. }
+            | Error::InvalidFingerprint { .. }
+            | Error::CertificatePinMismatch { .. } => "tls",
+            Error::InvalidIpRange { .. } | Error::BlockedAddress { .. } => "blocked_address",
+            Error::InvalidContentEncoding { .. } => "content_encoding",
+            Error::NonCompliant { .. } => "non_compliant",
+        }
+    }
+}
+
+impl From<http::uri::InvalidUri> for Error {
+    fn from(err: http::uri::InvalidUri) -> Error {
+        Error::InvalidUri(Arc::new(err))
+    }
+}
+
+/// Status code, headers and body excerpt of an unsuccessful response.
+#[derive(Debug, Clone)]
+pub struct ErrorResponse {
+    status: StatusCode,
+    url: Uri,
+    headers: HeaderMap,
+    body_excerpt: String,
+}
+
+impl ErrorResponse {
+    /// Creates the error response received from `url`, keeping at
+    /// most the first `ERROR_BODY_EXCERPT_SIZE` bytes of `body`.
+    pub(crate) fn new(
+        status: StatusCode,
+        url: Uri,
+        headers: HeaderMap,
+        body: &[u8],
+    ) -> ErrorResponse {
+        let excerpt = &body[..body.len().min(ERROR_BODY_EXCERPT_SIZE)];
+        let mut body_excerpt = String::from_utf8_lossy(excerpt).into_owned();
+        // A character cut at the end of the excerpt is replaced with
+        // a longer replacement character, that must be dropped.
+        let mut len = body_excerpt.len().min(ERROR_BODY_EXCERPT_SIZE);
+        while !body_excerpt.is_char_boundary(len) {
+            len -= 1;
+        }
+        body_excerpt.truncate(len);
+        ErrorResponse {
+            status,
+            url,
+            headers,
+            body_excerpt,
+        }
+    }
+
+    /// Returns the response status code.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Returns the url that sent the response.
+    pub fn url(&self) -> &Uri {
+        &self.url
+    }
+
+    /// Returns the response headers.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Returns the beginning of the response body, at most
+    /// `ERROR_BODY_EXCERPT_SIZE` bytes, decoded as UTF-8 with invalid
+    /// sequences replaced.
+    ///
+    /// The excerpt is empty if the body could not be read.
+    pub fn body_excerpt(&self) -> &str {
+        &self.body_excerpt
+    }
+}
+
+impl std::fmt::Display for ErrorResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} from {}", self.status, self.url)
+    }
+}
+
+/// Phase of a request that timed out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutPhase {
+    /// Establishing the connection with the host.
+    Connect,
+    /// Waiting for the response headers.
+    FirstByte,
+    /// Resolving the whole `stellar.toml` file.
+    Total,
+}
+
+impl std::fmt::Display for TimeoutPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimeoutPhase::Connect => write!(f, "connect"),
+            TimeoutPhase::FirstByte => write!(f, "first byte"),
+            TimeoutPhase::Total => write!(f, "total"),
+        }
+    }
 }
 
 mod option_display_fromstr {
@@ -535,7 +972,7 @@ mod option_display_fromstr {
     {
         match value {
             None => serializer.serialize_unit(),
-            Some(v) => serializer.serialize_str(&*v.to_string()),
+            Some(v) => serializer.serialize_str(&v.to_string()),
         }
     }
 }
@@ -562,6 +999,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_error_response_body_excerpt() {
+        let url: Uri = "https://example.org/.well-known/stellar.toml"
+            .parse()
+            .unwrap();
+        let excerpt = |body: &[u8]| {
+            let response =
+                ErrorResponse::new(StatusCode::BAD_GATEWAY, url.clone(), HeaderMap::new(), body);
+            response.body_excerpt().to_string()
+        };
+        assert_eq!("bad gateway", excerpt(b"bad gateway"));
+        assert_eq!("bad \u{fffd}", excerpt(b"bad \xff"));
+        // The last character is cut at `ERROR_BODY_EXCERPT_SIZE`.
+        let body = format!("a{}", "é".repeat(ERROR_BODY_EXCERPT_SIZE));
+        let expected = format!("a{}", "é".repeat(ERROR_BODY_EXCERPT_SIZE / 2 - 1));
+        assert_eq!(ERROR_BODY_EXCERPT_SIZE - 1, expected.len());
+        assert_eq!(expected, excerpt(body.as_bytes()));
+    }
+
+    #[cfg(feature = "hyper-transport")]
     #[tokio::test]
     async fn test_resolve_known_stellar_tomls() {
         let stellar = resolve("www.stellar.org").await.unwrap();
@@ -0,0 +1,261 @@
+use crate::{Error, FetchedStellarToml};
+use http::uri::Uri;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Whether a lookup was served from the cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheStatus {
+    /// The file was served from the cache, without sending a request.
+    Hit,
+    /// The cached file was served after the server confirmed that it
+    /// is still valid.
+    Revalidated,
+    /// The file was fetched, or the lookup failed.
+    Miss,
+}
+
+/// Outcome of a lookup, reported to `ResolverObserver::on_lookup`.
+#[derive(Debug)]
+pub struct LookupOutcome<'a> {
+    /// The url being resolved.
+    pub url: &'a Uri,
+    /// How long the lookup took, including redirects and retries.
+    pub elapsed: Duration,
+    /// Whether the file was served from the cache.
+    pub cache: CacheStatus,
+    /// The resolved file, or the error that made the lookup fail.
+    pub result: Result<&'a FetchedStellarToml, &'a Error>,
+}
+
+/// An observer notified of the outcome of every lookup, for example
+/// to export metrics.
+///
+/// Concurrent lookups of the same url are coalesced and reported once.
+///
+#[cfg_attr(feature = "hyper-transport", doc = "```rust")]
+#[cfg_attr(not(feature = "hyper-transport"), doc = "```ignore")]
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use stellar_toml::{LookupOutcome, ResolverObserver, StellarTomlClient};
+///
+/// /// Counts failed lookups.
+/// #[derive(Default)]
+/// struct Failures(AtomicUsize);
+///
+/// impl ResolverObserver for Failures {
+///     fn on_lookup(&self, outcome: &LookupOutcome<'_>) {
+///         if outcome.result.is_err() {
+///             self.0.fetch_add(1, Ordering::Relaxed);
+///         }
+///     }
+/// }
+///
+/// let client = StellarTomlClient::builder()
+///     .observer(Failures::default())
+///     .build();
+/// ```
+pub trait ResolverObserver: Send + Sync + 'static {
+    /// Called when a lookup completes, successfully or not.
+    ///
+    /// The method is called from the task running the lookup, and
+    /// should return quickly.
+    fn on_lookup(&self, outcome: &LookupOutcome<'_>);
+}
+
+/// A `ResolverObserver` shared between clients.
+#[derive(Clone)]
+pub(crate) struct SharedObserver(pub(crate) Arc<dyn ResolverObserver>);
+
+impl fmt::Debug for SharedObserver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ResolverObserver")
+    }
+}
+
+/// A `ResolverObserver` that records lookups with the `metrics`
+/// facade, to be exported by the recorder installed by the
+/// application.
+///
+/// The observer records:
+///
+/// - `stellar_toml_lookups_total`: counter of lookups, labeled with
+///   `outcome` (`success` or `error`) and `cache` (`hit`,
+///   `revalidated` or `miss`).
+/// - `stellar_toml_lookup_errors_total`: counter of failed lookups,
+///   labeled with the `kind` of error.
+/// - `stellar_toml_lookup_duration_seconds`: histogram of the lookup
+///   duration, labeled with `outcome`.
+/// - `stellar_toml_response_bytes`: histogram of the size of the
+///   resolved files.
+///
+/// Metrics are not labeled with the domain, to keep their
+/// cardinality bounded when resolving many domains.
+///
+/// ```rust
+/// use stellar_toml::{MetricsObserver, StellarTomlClient};
+///
+/// let client = StellarTomlClient::builder()
+///     .observer(MetricsObserver)
+///     .build();
+/// ```
+#[cfg(feature = "metrics")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MetricsObserver;
+
+#[cfg(feature = "metrics")]
+impl ResolverObserver for MetricsObserver {
+    fn on_lookup(&self, outcome: &LookupOutcome<'_>) {
+        let cache = match outcome.cache {
+            CacheStatus::Hit => "hit",
+            CacheStatus::Revalidated => "revalidated",
+            CacheStatus::Miss => "miss",
+        };
+        let result = match outcome.result {
+            Ok(fetched) => {
+                metrics::histogram!("stellar_toml_response_bytes")
+                    .record(fetched.body.len() as f64);
+                "success"
+            }
+            Err(err) => {
+                metrics::counter!("stellar_toml_lookup_errors_total", "kind" => err.kind())
+                    .increment(1);
+                "error"
+            }
+        };
+        metrics::counter!("stellar_toml_lookups_total", "outcome" => result, "cache" => cache)
+            .increment(1);
+        metrics::histogram!("stellar_toml_lookup_duration_seconds", "outcome" => result)
+            .record(outcome.elapsed.as_secs_f64());
+    }
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::*;
+    use crate::transport::{MemoryResponse, MemoryTransport};
+    use crate::{CacheConfig, ClientBuilder};
+    use metrics::{
+        Counter, CounterFn, Gauge, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder,
+        SharedString, Unit,
+    };
+    use std::sync::Mutex;
+
+    /// Records the metrics, as their name and labels followed by the
+    /// recorded value.
+    #[derive(Clone, Default)]
+    struct TestRecorder(Arc<Mutex<Vec<String>>>);
+
+    struct Handle {
+        key: String,
+        recorded: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl CounterFn for Handle {
+        fn increment(&self, value: u64) {
+            let line = format!("{} {}", self.key, value);
+            self.recorded.lock().unwrap().push(line);
+        }
+
+        fn absolute(&self, _value: u64) {}
+    }
+
+    impl HistogramFn for Handle {
+        fn record(&self, value: f64) {
+            let line = format!("{} {}", self.key, value);
+            self.recorded.lock().unwrap().push(line);
+        }
+    }
+
+    impl TestRecorder {
+        fn handle(&self, key: &Key) -> Arc<Handle> {
+            let labels: Vec<_> = key
+                .labels()
+                .map(|label| format!("{}={}", label.key(), label.value()))
+                .collect();
+            Arc::new(Handle {
+                key: format!("{}{{{}}}", key.name(), labels.join(",")),
+                recorded: self.0.clone(),
+            })
+        }
+    }
+
+    impl Recorder for TestRecorder {
+        fn describe_counter(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {
+        }
+
+        fn describe_gauge(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+        fn describe_histogram(
+            &self,
+            _key: KeyName,
+            _unit: Option<Unit>,
+            _description: SharedString,
+        ) {
+        }
+
+        fn register_counter(&self, key: &Key, _metadata: &Metadata<'_>) -> Counter {
+            Counter::from_arc(self.handle(key))
+        }
+
+        fn register_gauge(&self, _key: &Key, _metadata: &Metadata<'_>) -> Gauge {
+            Gauge::noop()
+        }
+
+        fn register_histogram(&self, key: &Key, _metadata: &Metadata<'_>) -> Histogram {
+            Histogram::from_arc(self.handle(key))
+        }
+    }
+
+    #[test]
+    fn test_metrics_observer() {
+        let url = "https://example.org/.well-known/stellar.toml";
+        let transport =
+            MemoryTransport::new().with_response(url, MemoryResponse::ok("VERSION = \"2.0.0\""));
+        let client = ClientBuilder::new()
+            .cache(CacheConfig::new())
+            .observer(MetricsObserver)
+            .build_with_transport(transport);
+        let recorder = TestRecorder::default();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        metrics::with_local_recorder(&recorder, || {
+            runtime.block_on(async {
+                client.resolve("example.org").await.unwrap();
+                client.resolve("example.org").await.unwrap();
+                client.resolve("missing.example.org").await.unwrap_err();
+            })
+        });
+
+        let recorded = recorder.0.lock().unwrap();
+        let has = |expected: &str| recorded.iter().any(|line| line == expected);
+        assert!(
+            has("stellar_toml_lookups_total{outcome=success,cache=miss} 1"),
+            "{:#?}",
+            recorded
+        );
+        assert!(
+            has("stellar_toml_lookups_total{outcome=success,cache=hit} 1"),
+            "{:#?}",
+            recorded
+        );
+        assert!(
+            has("stellar_toml_lookups_total{outcome=error,cache=miss} 1"),
+            "{:#?}",
+            recorded
+        );
+        assert!(
+            has("stellar_toml_lookup_errors_total{kind=client_error} 1"),
+            "{:#?}",
+            recorded
+        );
+        assert!(has("stellar_toml_response_bytes{} 17"), "{:#?}", recorded);
+        let durations = recorded
+            .iter()
+            .filter(|line| line.starts_with("stellar_toml_lookup_duration_seconds"))
+            .count();
+        assert_eq!(3, durations);
+    }
+}
@@ -0,0 +1,186 @@
+use crate::base64;
+use crate::Error;
+use serde_with::rust::display_fromstr;
+use sha2::{Digest, Sha256};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+const SHA256_PREFIX: &str = "sha256/";
+
+/// SHA-256 fingerprint of a certificate or of its public key.
+///
+/// Fingerprints are formatted as `sha256/` followed by the base64
+/// encoded hash, the format used by HPKP and most pinning libraries.
+/// The fingerprint of a certificate public key can be computed with:
+///
+/// ```text
+/// openssl x509 -in cert.pem -pubkey -noout \
+///   | openssl pkey -pubin -outform der \
+///   | openssl dgst -sha256 -binary | base64
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fingerprint([u8; 32]);
+
+/// Fingerprints of a certificate sent by the server.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerCertificate {
+    /// Fingerprint of the DER encoded certificate.
+    #[serde(with = "display_fromstr")]
+    pub fingerprint: Fingerprint,
+    /// Fingerprint of the DER encoded certificate public key, the
+    /// `SubjectPublicKeyInfo`.
+    #[serde(with = "display_fromstr")]
+    pub public_key: Fingerprint,
+}
+
+/// Certificate chain of a tls connection, stored in the extensions
+/// of its responses.
+#[derive(Debug, Clone)]
+pub(crate) struct PeerCertificates(pub(crate) Vec<PeerCertificate>);
+
+impl Fingerprint {
+    /// Creates a fingerprint from the SHA-256 hash.
+    pub fn from_sha256(hash: [u8; 32]) -> Fingerprint {
+        Fingerprint(hash)
+    }
+
+    /// Computes the fingerprint of `bytes`.
+    pub fn of(bytes: &[u8]) -> Fingerprint {
+        Fingerprint(Sha256::digest(bytes).into())
+    }
+
+    /// Returns the SHA-256 hash.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl FromStr for Fingerprint {
+    type Err = Error;
+
+    /// Parses a fingerprint, with or without the `sha256/` prefix.
+    fn from_str(s: &str) -> Result<Fingerprint, Error> {
+        let invalid = || Error::InvalidFingerprint {
+            fingerprint: s.to_string(),
+        };
+        let encoded = s.strip_prefix(SHA256_PREFIX).unwrap_or(s);
+        let hash = base64::decode(encoded).ok_or_else(invalid)?;
+        let hash = <[u8; 32]>::try_from(hash.as_slice()).map_err(|_| invalid())?;
+        Ok(Fingerprint(hash))
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", SHA256_PREFIX, base64::encode(&self.0))
+    }
+}
+
+impl fmt::Debug for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Fingerprint({})", self)
+    }
+}
+
+#[cfg(feature = "hyper-transport")]
+impl PeerCertificate {
+    /// Computes the fingerprints of the DER encoded certificate `der`.
+    ///
+    /// Returns `None` if `der` is not a valid X.509 certificate.
+    pub(crate) fn from_der(der: &[u8]) -> Option<PeerCertificate> {
+        Some(PeerCertificate {
+            fingerprint: Fingerprint::of(der),
+            public_key: Fingerprint::of(subject_public_key_info(der)?),
+        })
+    }
+}
+
+/// Returns `true` if the public key of any certificate in `chain` is
+/// in `pins`.
+pub(crate) fn matches_pins(pins: &[Fingerprint], chain: &[PeerCertificate]) -> bool {
+    chain
+        .iter()
+        .any(|certificate| pins.contains(&certificate.public_key))
+}
+
+/// Returns the `SubjectPublicKeyInfo` of the DER encoded X.509
+/// certificate `der`, including its tag and length.
+#[cfg(feature = "hyper-transport")]
+fn subject_public_key_info(der: &[u8]) -> Option<&[u8]> {
+    const SEQUENCE: u8 = 0x30;
+    const INTEGER: u8 = 0x02;
+    const VERSION: u8 = 0xa0;
+
+    let (certificate, _) = read_der(der, SEQUENCE)?;
+    let (tbs_certificate, _) = read_der(certificate, SEQUENCE)?;
+    let mut fields = tbs_certificate;
+    if fields.first() == Some(&VERSION) {
+        fields = read_der(fields, VERSION)?.1;
+    }
+    // Skip the serial number, signature algorithm, issuer, validity
+    // and subject.
+    for tag in &[INTEGER, SEQUENCE, SEQUENCE, SEQUENCE, SEQUENCE] {
+        fields = read_der(fields, *tag)?.1;
+    }
+    let (_, rest) = read_der(fields, SEQUENCE)?;
+    Some(&fields[..fields.len() - rest.len()])
+}
+
+/// Reads a DER value with the given `tag` from `input`, returning its
+/// contents and the remaining input.
+#[cfg(feature = "hyper-transport")]
+fn read_der(input: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    if *input.first()? != tag {
+        return None;
+    }
+    let first = *input.get(1)?;
+    let (len, header_len) = if first < 0x80 {
+        (usize::from(first), 2)
+    } else {
+        let len_len = usize::from(first & 0x7f);
+        if len_len == 0 || len_len > 4 {
+            return None;
+        }
+        let len = input
+            .get(2..2 + len_len)?
+            .iter()
+            .fold(0usize, |len, byte| (len << 8) | usize::from(*byte));
+        (len, 2 + len_len)
+    };
+    let end = header_len.checked_add(len)?;
+    Some((input.get(header_len..end)?, &input[end..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "hyper-transport")]
+    #[test]
+    fn test_peer_certificate_from_der() {
+        let certificate = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let der = certificate.serialize_der().unwrap();
+        let peer = PeerCertificate::from_der(&der).unwrap();
+        assert_eq!(Fingerprint::of(&der), peer.fingerprint);
+        assert_eq!(
+            Fingerprint::of(&certificate.get_key_pair().public_key_der()),
+            peer.public_key
+        );
+        assert!(PeerCertificate::from_der(&der[..der.len() - 1]).is_none());
+        assert!(PeerCertificate::from_der(b"").is_none());
+    }
+
+    #[test]
+    fn test_fingerprint_from_str() {
+        let fingerprint = Fingerprint::of(b"public key");
+        let formatted = fingerprint.to_string();
+        assert!(formatted.starts_with("sha256/"));
+        assert_eq!(fingerprint, formatted.parse().unwrap());
+        assert_eq!(fingerprint, formatted["sha256/".len()..].parse().unwrap());
+        assert!(matches!(
+            "sha256/AAAA".parse::<Fingerprint>(),
+            Err(Error::InvalidFingerprint { .. })
+        ));
+    }
+}
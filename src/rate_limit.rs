@@ -0,0 +1,141 @@
+use crate::timer::SharedTimer;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Maximum number of buckets kept before pruning the full ones.
+const MAX_IDLE_BUCKETS: usize = 1024;
+
+/// Maximum rate of requests sent to the same host.
+///
+/// The limit is implemented as a token bucket: each host can receive
+/// `burst` requests at once, after which requests are delayed to
+/// respect the rate.
+///
+#[cfg_attr(feature = "hyper-transport", doc = "```rust")]
+#[cfg_attr(not(feature = "hyper-transport"), doc = "```ignore")]
+/// use std::time::Duration;
+/// use stellar_toml::{RateLimit, StellarTomlClient};
+///
+/// // At most 2 requests per second to each host.
+/// let client = StellarTomlClient::builder()
+///     .rate_limit(RateLimit::new(2, Duration::from_secs(1)))
+///     .build();
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimit {
+    /// Tokens added to the bucket per second.
+    rate: f64,
+    /// Maximum number of tokens in the bucket.
+    burst: f64,
+}
+
+impl RateLimit {
+    /// Creates a new limit of `requests` requests every `period`, with
+    /// a burst of `requests` requests.
+    pub fn new(requests: u32, period: Duration) -> RateLimit {
+        let requests = requests.max(1);
+        RateLimit {
+            rate: f64::from(requests) / period.as_secs_f64(),
+            burst: f64::from(requests),
+        }
+    }
+
+    /// Sets the maximum number of requests that can be sent at once.
+    pub fn burst(mut self, burst: u32) -> RateLimit {
+        self.burst = f64::from(burst.max(1));
+        self
+    }
+}
+
+/// Per-host token bucket rate limiter.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    limit: RateLimit,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(limit: RateLimit) -> RateLimiter {
+        RateLimiter {
+            limit,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Waits until a request can be sent to `host`.
+    pub(crate) async fn acquire(&self, host: &str, timer: &SharedTimer) {
+        let wait = self.reserve(host, Instant::now());
+        if wait > Duration::from_secs(0) {
+            timer.sleep(wait).await;
+        }
+    }
+
+    /// Takes a token from the bucket of `host` and returns how long
+    /// the caller must wait before using it.
+    ///
+    /// The bucket can go negative, so that callers are served in the
+    /// order they reserved a token.
+    fn reserve(&self, host: &str, now: Instant) -> Duration {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() > MAX_IDLE_BUCKETS {
+            let limit = &self.limit;
+            buckets.retain(|_, bucket| bucket.refill(limit, now) < limit.burst);
+        }
+        let bucket = buckets
+            .entry(host.to_ascii_lowercase())
+            .or_insert_with(|| Bucket {
+                tokens: self.limit.burst,
+                updated_at: now,
+            });
+        bucket.refill(&self.limit, now);
+        bucket.tokens -= 1.0;
+        if bucket.tokens >= 0.0 {
+            Duration::from_secs(0)
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / self.limit.rate)
+        }
+    }
+}
+
+impl Bucket {
+    fn refill(&mut self, limit: &RateLimit, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.rate).min(limit.burst);
+        self.updated_at = now;
+        self.tokens
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reserve_respects_rate_and_burst() {
+        let limiter = RateLimiter::new(RateLimit::new(10, Duration::from_secs(1)).burst(2));
+        let now = Instant::now();
+        assert_eq!(Duration::from_secs(0), limiter.reserve("example.org", now));
+        assert_eq!(Duration::from_secs(0), limiter.reserve("EXAMPLE.org", now));
+        assert_eq!(
+            Duration::from_millis(100),
+            limiter.reserve("example.org", now)
+        );
+        assert_eq!(
+            Duration::from_millis(200),
+            limiter.reserve("example.org", now)
+        );
+        assert_eq!(Duration::from_secs(0), limiter.reserve("other.org", now));
+        let later = now + Duration::from_secs(1);
+        assert_eq!(
+            Duration::from_secs(0),
+            limiter.reserve("example.org", later)
+        );
+    }
+}
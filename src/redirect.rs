@@ -0,0 +1,191 @@
+use crate::Error;
+use http::header::LOCATION;
+use http::uri::{Parts, PathAndQuery, Scheme, Uri};
+use http::{HeaderMap, StatusCode};
+
+/// Policy used to follow redirects when resolving a `stellar.toml` file.
+///
+/// Redirects from https to http are never followed, regardless of
+/// the policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RedirectPolicy {
+    /// Do not follow redirects.
+    #[default]
+    None,
+    /// Follow at most the given number of redirects, only if they
+    /// point to the same host as the original url.
+    SameHost(usize),
+    /// Follow at most the given number of redirects.
+    Limited(usize),
+}
+
+impl RedirectPolicy {
+    /// Checks if the redirect from `from` to `to` can be followed,
+    /// given the `original` url and the number of redirects already
+    /// followed.
+    pub(crate) fn check(
+        &self,
+        original: &Uri,
+        from: &Uri,
+        to: &Uri,
+        followed: usize,
+    ) -> Result<(), Error> {
+        if from.scheme() == Some(&Scheme::HTTPS) && to.scheme() != Some(&Scheme::HTTPS) {
+            return Err(Error::InsecureRedirect { url: to.clone() });
+        }
+        let limit = match self {
+            RedirectPolicy::None => 0,
+            RedirectPolicy::SameHost(limit) => {
+                if !is_same_host(original, to) {
+                    return Err(Error::CrossHostRedirect { url: to.clone() });
+                }
+                *limit
+            }
+            RedirectPolicy::Limited(limit) => *limit,
+        };
+        if followed >= limit {
+            return Err(Error::TooManyRedirects {
+                limit,
+                url: original.clone(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Returns `true` if `status` is a redirect that should be followed.
+pub(crate) fn is_redirect(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::MOVED_PERMANENTLY
+            | StatusCode::FOUND
+            | StatusCode::SEE_OTHER
+            | StatusCode::TEMPORARY_REDIRECT
+            | StatusCode::PERMANENT_REDIRECT
+    )
+}
+
+/// Returns the url the response `headers` redirect to, resolved
+/// relative to `base`.
+pub(crate) fn redirect_location(base: &Uri, headers: &HeaderMap) -> Result<Uri, Error> {
+    let invalid = || Error::InvalidRedirect { url: base.clone() };
+    let location = headers
+        .get(LOCATION)
+        .and_then(|value| value.to_str().ok())
+        .ok_or_else(invalid)?;
+    if has_scheme(location) {
+        return location.parse().map_err(|_| invalid());
+    }
+    if let Some(rest) = location.strip_prefix("//") {
+        let scheme = base.scheme_str().unwrap_or("https");
+        return format!("{}://{}", scheme, rest)
+            .parse()
+            .map_err(|_| invalid());
+    }
+    let path = if location.starts_with('/') {
+        location.to_string()
+    } else {
+        let base_path = base.path();
+        let dir = &base_path[..base_path.rfind('/').map(|i| i + 1).unwrap_or(0)];
+        format!("{}{}", dir, location)
+    };
+    let mut parts = Parts::default();
+    parts.scheme = base.scheme().cloned();
+    parts.authority = base.authority().cloned();
+    parts.path_and_query = Some(path.parse::<PathAndQuery>().map_err(|_| invalid())?);
+    Uri::from_parts(parts).map_err(|_| invalid())
+}
+
+/// Returns `true` if `location` starts with a scheme, and is thus an
+/// absolute url rather than a relative reference.
+fn has_scheme(location: &str) -> bool {
+    let scheme = match location.split_once(':') {
+        Some((scheme, _)) => scheme,
+        None => return false,
+    };
+    let mut chars = scheme.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+}
+
+fn is_same_host(a: &Uri, b: &Uri) -> bool {
+    match (a.host(), b.host()) {
+        (Some(a), Some(b)) => a.eq_ignore_ascii_case(b),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header::HeaderValue;
+
+    fn location(base: &str, location: &str) -> Result<Uri, Error> {
+        let mut headers = HeaderMap::new();
+        headers.insert(LOCATION, HeaderValue::from_str(location).unwrap());
+        redirect_location(&base.parse().unwrap(), &headers)
+    }
+
+    #[test]
+    fn test_redirect_location() {
+        let base = "https://example.org/.well-known/stellar.toml";
+        assert_eq!(
+            "https://www.example.org/.well-known/stellar.toml",
+            location(base, "https://www.example.org/.well-known/stellar.toml")
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            "https://www.example.org/stellar.toml",
+            location(base, "//www.example.org/stellar.toml")
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            "https://example.org/stellar.toml",
+            location(base, "/stellar.toml").unwrap().to_string()
+        );
+        assert_eq!(
+            "https://example.org/.well-known/other.toml",
+            location(base, "other.toml").unwrap().to_string()
+        );
+        assert_eq!(
+            "https://example.org/r?next=https://www.example.org/",
+            location(base, "/r?next=https://www.example.org/")
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            "https://example.org/.well-known/r?next=https://www.example.org/",
+            location(base, "r?next=https://www.example.org/")
+                .unwrap()
+                .to_string()
+        );
+        assert!(redirect_location(&base.parse().unwrap(), &HeaderMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_redirect_policy_never_downgrades() {
+        let from: Uri = "https://example.org/".parse().unwrap();
+        let to: Uri = "http://example.org/".parse().unwrap();
+        let result = RedirectPolicy::Limited(10).check(&from, &from, &to, 0);
+        assert!(matches!(result, Err(Error::InsecureRedirect { .. })));
+    }
+
+    #[test]
+    fn test_redirect_policy_same_host() {
+        let from: Uri = "https://example.org/a".parse().unwrap();
+        let same: Uri = "https://EXAMPLE.org/b".parse().unwrap();
+        let other: Uri = "https://www.example.org/a".parse().unwrap();
+        let policy = RedirectPolicy::SameHost(1);
+        assert!(policy.check(&from, &from, &same, 0).is_ok());
+        assert!(matches!(
+            policy.check(&from, &from, &same, 1),
+            Err(Error::TooManyRedirects { limit: 1, .. })
+        ));
+        assert!(matches!(
+            policy.check(&from, &from, &other, 0),
+            Err(Error::CrossHostRedirect { .. })
+        ));
+    }
+}
@@ -0,0 +1,232 @@
+use crate::{Error, TimeoutPhase};
+use http::header::RETRY_AFTER;
+use http::StatusCode;
+use std::time::{Duration, SystemTime};
+
+/// Policy used to retry failed lookups.
+///
+/// Only transient errors are retried: refused, reset or interrupted
+/// connections, connect and first byte timeouts, and `429`, `502`,
+/// `503` and `504` responses. Failures to resolve the host or to
+/// verify its certificate are not retried.
+/// The delay between attempts grows exponentially, and honours the
+/// `Retry-After` header of the response, if present.
+///
+#[cfg_attr(feature = "hyper-transport", doc = "```rust")]
+#[cfg_attr(not(feature = "hyper-transport"), doc = "```ignore")]
+/// use std::time::Duration;
+/// use stellar_toml::{RetryPolicy, StellarTomlClient};
+///
+/// let client = StellarTomlClient::builder()
+///     .retry_policy(RetryPolicy::new(3).initial_backoff(Duration::from_millis(200)))
+///     .build();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: bool,
+}
+
+impl RetryPolicy {
+    /// Creates a new policy that makes at most `max_attempts` attempts,
+    /// including the first one.
+    pub fn new(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            jitter: true,
+        }
+    }
+
+    /// Sets the delay before the first retry. Defaults to 100 milliseconds.
+    pub fn initial_backoff(mut self, backoff: Duration) -> RetryPolicy {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Sets the maximum delay between two attempts, including delays
+    /// requested with `Retry-After`. Defaults to 10 seconds.
+    pub fn max_backoff(mut self, backoff: Duration) -> RetryPolicy {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Sets whether to randomize the delay between attempts. Defaults to `true`.
+    pub fn jitter(mut self, jitter: bool) -> RetryPolicy {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Returns the maximum number of attempts.
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Returns `true` if `err`, returned by attempt number `attempt`,
+    /// should be retried.
+    pub(crate) fn should_retry(&self, attempt: u32, err: &Error) -> bool {
+        attempt < self.max_attempts && is_transient(err)
+    }
+
+    /// Returns the delay before retrying attempt number `attempt`,
+    /// that failed with `err`.
+    pub(crate) fn delay(&self, attempt: u32, err: &Error) -> Duration {
+        if let Some(retry_after) = retry_after(err) {
+            return retry_after.min(self.max_backoff);
+        }
+        let exponent = attempt.saturating_sub(1).min(31);
+        let backoff = self
+            .initial_backoff
+            .checked_mul(1 << exponent)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff);
+        if self.jitter {
+            let half = backoff / 2;
+            half + half.mul_f64(fastrand::f64())
+        } else {
+            backoff
+        }
+    }
+}
+
+/// Returns `true` if `err` is a transient error that may succeed if retried.
+pub(crate) fn is_transient(err: &Error) -> bool {
+    match err {
+        Error::ServerError(response) | Error::ClientError(response) => matches!(
+            response.status(),
+            StatusCode::TOO_MANY_REQUESTS
+                | StatusCode::BAD_GATEWAY
+                | StatusCode::SERVICE_UNAVAILABLE
+                | StatusCode::GATEWAY_TIMEOUT
+        ),
+        Error::Timeout { phase, .. } => *phase != TimeoutPhase::Total,
+        #[cfg(feature = "hyper-transport")]
+        Error::TransportError { source, .. } => match source.downcast_ref::<hyper::Error>() {
+            Some(err) => err.is_incomplete_message() || is_transient_io(err),
+            None => false,
+        },
+        _ => false,
+    }
+}
+
+/// Returns `true` if `err` was caused by a connection that was
+/// refused, reset or timed out.
+///
+/// Other connection errors, such as failures to resolve the host or
+/// to verify its certificate, are permanent.
+#[cfg(feature = "hyper-transport")]
+fn is_transient_io(err: &hyper::Error) -> bool {
+    use std::io;
+
+    let mut source = std::error::Error::source(err);
+    while let Some(err) = source {
+        if let Some(io_err) = err.downcast_ref::<io::Error>() {
+            return matches!(
+                io_err.kind(),
+                io::ErrorKind::ConnectionRefused
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::TimedOut
+            );
+        }
+        source = err.source();
+    }
+    false
+}
+
+/// Returns the delay requested by the `Retry-After` header of the
+/// response contained in `err`.
+fn retry_after(err: &Error) -> Option<Duration> {
+    let response = match err {
+        Error::ServerError(response) | Error::ClientError(response) => response,
+        _ => return None,
+    };
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or(Duration::from_secs(0)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorResponse;
+
+    fn error(status: StatusCode, retry_after: Option<&str>) -> Error {
+        let mut headers = http::HeaderMap::new();
+        if let Some(value) = retry_after {
+            headers.insert(RETRY_AFTER, value.parse().unwrap());
+        }
+        let url = "https://example.org/.well-known/stellar.toml"
+            .parse()
+            .unwrap();
+        Error::ServerError(Box::new(ErrorResponse::new(status, url, headers, b"")))
+    }
+
+    #[test]
+    fn test_is_transient() {
+        assert!(is_transient(&error(StatusCode::SERVICE_UNAVAILABLE, None)));
+        assert!(is_transient(&error(StatusCode::BAD_GATEWAY, None)));
+        assert!(!is_transient(&error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            None
+        )));
+        let url: http::Uri = "https://example.org".parse().unwrap();
+        assert!(is_transient(&Error::Timeout {
+            phase: TimeoutPhase::Connect,
+            url: url.clone(),
+        }));
+        assert!(!is_transient(&Error::Timeout {
+            phase: TimeoutPhase::Total,
+            url,
+        }));
+    }
+
+    #[test]
+    fn test_exponential_backoff() {
+        let policy = RetryPolicy::new(10)
+            .initial_backoff(Duration::from_millis(100))
+            .max_backoff(Duration::from_millis(1000))
+            .jitter(false);
+        let err = error(StatusCode::SERVICE_UNAVAILABLE, None);
+        assert_eq!(Duration::from_millis(100), policy.delay(1, &err));
+        assert_eq!(Duration::from_millis(200), policy.delay(2, &err));
+        assert_eq!(Duration::from_millis(400), policy.delay(3, &err));
+        assert_eq!(Duration::from_millis(1000), policy.delay(5, &err));
+        assert_eq!(Duration::from_millis(1000), policy.delay(40, &err));
+    }
+
+    #[test]
+    fn test_backoff_jitter() {
+        let policy = RetryPolicy::new(10).initial_backoff(Duration::from_millis(100));
+        let err = error(StatusCode::SERVICE_UNAVAILABLE, None);
+        for _ in 0..100 {
+            let delay = policy.delay(1, &err);
+            assert!(delay >= Duration::from_millis(50));
+            assert!(delay <= Duration::from_millis(100));
+        }
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let policy = RetryPolicy::new(10).max_backoff(Duration::from_secs(5));
+        let err = error(StatusCode::SERVICE_UNAVAILABLE, Some("2"));
+        assert_eq!(Duration::from_secs(2), policy.delay(1, &err));
+        let err = error(StatusCode::SERVICE_UNAVAILABLE, Some("120"));
+        assert_eq!(Duration::from_secs(5), policy.delay(1, &err));
+        let err = error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            Some("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        assert_eq!(Duration::from_secs(0), policy.delay(1, &err));
+    }
+}
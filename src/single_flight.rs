@@ -0,0 +1,73 @@
+use crate::{Error, FetchedStellarToml};
+use futures_util::future::{BoxFuture, FutureExt, Shared};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::Mutex;
+
+type SharedLookup = Shared<BoxFuture<'static, Result<FetchedStellarToml, Error>>>;
+
+/// Coalesces concurrent lookups of the same url, so that only one
+/// of them is performed and all callers receive its result.
+#[derive(Default)]
+pub(crate) struct SingleFlight {
+    inflight: Mutex<HashMap<String, Inflight>>,
+}
+
+struct Inflight {
+    lookup: SharedLookup,
+    waiters: usize,
+}
+
+/// Removes the in-flight lookup once its last waiter completes or
+/// is dropped, so that a cancelled lookup is never resumed later.
+struct Waiter<'a> {
+    flight: &'a SingleFlight,
+    key: String,
+}
+
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        let mut inflight = self.flight.inflight.lock().unwrap();
+        if let Some(entry) = inflight.get_mut(&self.key) {
+            entry.waiters -= 1;
+            if entry.waiters == 0 {
+                inflight.remove(&self.key);
+            }
+        }
+    }
+}
+
+impl SingleFlight {
+    /// Returns the result of the in-flight lookup for `key` or, if
+    /// there is none, starts a new lookup with `lookup`.
+    pub(crate) async fn run<F>(
+        &self,
+        key: String,
+        lookup: impl FnOnce() -> F,
+    ) -> Result<FetchedStellarToml, Error>
+    where
+        F: Future<Output = Result<FetchedStellarToml, Error>> + Send + 'static,
+    {
+        let shared = {
+            let mut inflight = self.inflight.lock().unwrap();
+            let entry = inflight.entry(key.clone()).or_insert_with(|| Inflight {
+                lookup: lookup().boxed().shared(),
+                waiters: 0,
+            });
+            entry.waiters += 1;
+            entry.lookup.clone()
+        };
+        let _waiter = Waiter { flight: self, key };
+        shared.await
+    }
+}
+
+impl fmt::Debug for SingleFlight {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inflight = self.inflight.lock().unwrap();
+        f.debug_struct("SingleFlight")
+            .field("inflight", &inflight.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
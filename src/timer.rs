@@ -0,0 +1,290 @@
+use futures_util::future::{self, Either};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Future returned by `Timer::sleep`.
+pub type SleepFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// A timer used by the client to enforce timeouts and to delay
+/// retries and rate limited requests.
+///
+/// The client uses `TokioTimer` when the `tokio` dependency is enabled,
+/// which is the case with the default features, and `ThreadTimer`
+/// otherwise. Set the timer of your executor with
+/// `ClientBuilder::timer` to resolve files with a custom `Transport`
+/// outside of a `tokio` runtime.
+pub trait Timer: Send + Sync + 'static {
+    /// Returns a future that completes after `duration`.
+    fn sleep(&self, duration: Duration) -> SleepFuture;
+}
+
+/// A `Timer` backed by the `tokio` runtime.
+///
+/// Its futures must be polled within a `tokio` runtime.
+#[cfg(feature = "tokio")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioTimer;
+
+#[cfg(feature = "tokio")]
+impl Timer for TokioTimer {
+    fn sleep(&self, duration: Duration) -> SleepFuture {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// A `Timer` that works with any executor.
+///
+/// The timer starts a background thread the first time it is used,
+/// that wakes the sleeping futures when they expire. Clones of the
+/// timer share the same thread.
+#[derive(Debug, Clone, Default)]
+pub struct ThreadTimer {
+    sender: Arc<Mutex<Option<Sender<Entry>>>>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    deadline: Instant,
+    state: Arc<Mutex<SleepState>>,
+}
+
+#[derive(Debug, Default)]
+struct SleepState {
+    expired: bool,
+    waker: Option<Waker>,
+}
+
+/// Future returned by `ThreadTimer::sleep`.
+struct ThreadSleep {
+    state: Arc<Mutex<SleepState>>,
+}
+
+impl Timer for ThreadTimer {
+    fn sleep(&self, duration: Duration) -> SleepFuture {
+        let state = Arc::new(Mutex::new(SleepState::default()));
+        let entry = Entry {
+            deadline: Instant::now() + duration,
+            state: state.clone(),
+        };
+        let mut sender = self.sender.lock().unwrap();
+        let sender = sender.get_or_insert_with(|| {
+            let (sender, receiver) = mpsc::channel();
+            thread::Builder::new()
+                .name("stellar-toml-timer".to_string())
+                .spawn(move || run_timer(receiver))
+                .expect("failed to start the timer thread");
+            sender
+        });
+        // The thread runs until the timer is dropped.
+        sender.send(entry).unwrap();
+        Box::pin(ThreadSleep { state })
+    }
+}
+
+impl Future for ThreadSleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.expired {
+            return Poll::Ready(());
+        }
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+/// Wakes the entries received on `receiver` when they expire, until
+/// the timer is dropped and all entries expired.
+fn run_timer(receiver: Receiver<Entry>) {
+    // Deadlines are ordered by their index when equal, the index is
+    // the key of their state.
+    let mut deadlines = BinaryHeap::new();
+    let mut states = HashMap::new();
+    let mut index = 0u64;
+    let mut connected = true;
+    loop {
+        let now = Instant::now();
+        while let Some(&Reverse((deadline, expired))) = deadlines.peek() {
+            if deadline > now {
+                break;
+            }
+            deadlines.pop();
+            if let Some(state) = states.remove(&expired) {
+                expire(state);
+            }
+        }
+        let next = deadlines.peek().map(|&Reverse((deadline, _))| deadline);
+        let received = match (next, connected) {
+            (None, false) => return,
+            (Some(deadline), false) => {
+                thread::sleep(deadline - now);
+                continue;
+            }
+            (None, true) => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            (Some(deadline), true) => receiver.recv_timeout(deadline - now),
+        };
+        match received {
+            Ok(entry) => {
+                index += 1;
+                deadlines.push(Reverse((entry.deadline, index)));
+                states.insert(index, entry.state);
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => connected = false,
+        }
+    }
+}
+
+fn expire(state: Arc<Mutex<SleepState>>) {
+    let mut state = state.lock().unwrap();
+    state.expired = true;
+    if let Some(waker) = state.waker.take() {
+        waker.wake();
+    }
+}
+
+/// A `Timer` shared between clients.
+#[derive(Clone)]
+pub(crate) struct SharedTimer(pub(crate) Arc<dyn Timer>);
+
+impl SharedTimer {
+    pub(crate) fn sleep(&self, duration: Duration) -> SleepFuture {
+        self.0.sleep(duration)
+    }
+
+    /// Runs `future`, returning `None` if it does not complete
+    /// within `duration`.
+    pub(crate) async fn timeout<F: Future>(
+        &self,
+        duration: Duration,
+        future: F,
+    ) -> Option<F::Output> {
+        let future = Box::pin(future);
+        match future::select(future, self.sleep(duration)).await {
+            Either::Left((output, _)) => Some(output),
+            Either::Right(_) => None,
+        }
+    }
+}
+
+impl Default for SharedTimer {
+    #[cfg(feature = "tokio")]
+    fn default() -> SharedTimer {
+        SharedTimer(Arc::new(TokioTimer))
+    }
+
+    #[cfg(not(feature = "tokio"))]
+    fn default() -> SharedTimer {
+        SharedTimer(Arc::new(ThreadTimer::default()))
+    }
+}
+
+impl fmt::Debug for SharedTimer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Timer")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{MemoryResponse, MemoryTransport, ResponseFuture, Transport};
+    use crate::{CacheConfig, ClientBuilder, Error, RetryPolicy, TimeoutPhase};
+    use http::{Request, StatusCode};
+    use std::task::Wake;
+
+    /// Wakes the thread blocked in `block_on`.
+    struct ThreadWaker(thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    /// Minimal executor, to check that resolution doesn't depend on
+    /// the `tokio` runtime.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            thread::park();
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    struct PendingTransport;
+
+    impl Transport for PendingTransport {
+        fn send(&self, _request: Request<()>) -> ResponseFuture {
+            Box::pin(future::pending())
+        }
+    }
+
+    #[test]
+    fn test_thread_timer_sleep() {
+        let timer = ThreadTimer::default();
+        let start = Instant::now();
+        let sleeps = future::join(
+            timer.sleep(Duration::from_millis(50)),
+            timer.clone().sleep(Duration::from_millis(10)),
+        );
+        block_on(sleeps);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        let timer = SharedTimer(Arc::new(timer));
+        let result = block_on(timer.timeout(Duration::from_millis(10), future::pending::<()>()));
+        assert_eq!(None, result);
+        let result = block_on(timer.timeout(Duration::from_secs(10), future::ready(1)));
+        assert_eq!(Some(1), result);
+    }
+
+    #[test]
+    fn test_resolve_without_tokio() {
+        let url = "https://example.org/.well-known/stellar.toml";
+        let transport = MemoryTransport::new();
+        transport.push(url, MemoryResponse::new(StatusCode::BAD_GATEWAY, ""));
+        transport.push(url, MemoryResponse::ok("VERSION = \"2.0.0\""));
+        let directory = std::env::temp_dir().join(format!("stellar-toml-{}", fastrand::u64(..)));
+        let cache = CacheConfig::new().directory(&directory);
+        let client = ClientBuilder::new()
+            .timer(ThreadTimer::default())
+            .retry_policy(RetryPolicy::new(2).initial_backoff(Duration::from_millis(1)))
+            .cache(cache.clone())
+            .build_with_transport(transport);
+        let stellar = block_on(client.resolve("example.org")).unwrap();
+        assert_eq!(Some("2.0.0".to_string()), stellar.version);
+
+        // The disk cache is read and written on another thread.
+        let client = ClientBuilder::new()
+            .timer(ThreadTimer::default())
+            .cache(cache)
+            .offline(true)
+            .build_with_transport(MemoryTransport::new());
+        let stellar = block_on(client.resolve("example.org")).unwrap();
+        assert_eq!(Some("2.0.0".to_string()), stellar.version);
+        std::fs::remove_dir_all(directory).unwrap();
+
+        let client = ClientBuilder::new()
+            .timer(ThreadTimer::default())
+            .first_byte_timeout(Duration::from_millis(10))
+            .build_with_transport(PendingTransport);
+        match block_on(client.resolve("example.org")) {
+            Err(Error::Timeout { phase, .. }) => assert_eq!(TimeoutPhase::FirstByte, phase),
+            other => panic!("expected timeout, got {:?}", other),
+        }
+    }
+}
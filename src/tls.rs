@@ -0,0 +1,446 @@
+use crate::base64;
+use crate::client::Config;
+use crate::pinning::{PeerCertificate, PeerCertificates};
+use crate::proxy::BoxError;
+use crate::trace::Instrument;
+use crate::transport::Connector;
+use crate::Error;
+use http::uri::Uri;
+use hyper::client::connect::{Connected, Connection};
+use hyper::service::Service;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+
+const PEM_BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+const PEM_END: &str = "-----END CERTIFICATE-----";
+
+#[cfg(feature = "rustls-tls")]
+type HttpsConnector = hyper_rustls::HttpsConnector<Connector>;
+#[cfg(feature = "rustls-tls")]
+type HttpsStream = hyper_rustls::MaybeHttpsStream<TcpStream>;
+
+#[cfg(all(feature = "native-tls", not(feature = "rustls-tls")))]
+type HttpsConnector = hyper_tls::HttpsConnector<Connector>;
+#[cfg(all(feature = "native-tls", not(feature = "rustls-tls")))]
+type HttpsStream = hyper_tls::MaybeHttpsStream<TcpStream>;
+
+/// Connector used by `HyperTransport` to establish https connections.
+///
+/// The certificates sent by the server are stored in the extensions
+/// of the responses received on the connection.
+#[derive(Debug, Clone)]
+pub(crate) struct TlsConnector {
+    https: HttpsConnector,
+}
+
+/// A connection established by `TlsConnector`.
+pub(crate) struct TlsStream {
+    inner: HttpsStream,
+    peer_certificates: Option<PeerCertificates>,
+}
+
+/// A root certificate trusted when verifying https connections.
+///
+/// ```rust
+/// use stellar_toml::{Certificate, StellarTomlClient};
+///
+/// # fn run(pem: &[u8]) -> std::result::Result<(), stellar_toml::Error> {
+/// // Trust only the staging CA.
+/// let client = StellarTomlClient::builder()
+///     .add_root_certificate(Certificate::from_pem(pem)?)
+///     .tls_built_in_root_certs(false)
+///     .build();
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct Certificate {
+    der: Vec<u8>,
+}
+
+impl Certificate {
+    /// Creates a certificate from its DER encoding.
+    pub fn from_der(der: &[u8]) -> Result<Certificate, Error> {
+        let certificate = Certificate { der: der.to_vec() };
+        certificate.validate()?;
+        Ok(certificate)
+    }
+
+    /// Creates a certificate from the first certificate in a PEM file.
+    pub fn from_pem(pem: &[u8]) -> Result<Certificate, Error> {
+        Certificate::from_pem_bundle(pem)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| invalid("no certificate found"))
+    }
+
+    /// Creates certificates from all the certificates in a PEM file,
+    /// for example a CA bundle.
+    pub fn from_pem_bundle(pem: &[u8]) -> Result<Vec<Certificate>, Error> {
+        let mut pem = std::str::from_utf8(pem).map_err(|_| invalid("invalid PEM"))?;
+        let mut certificates = Vec::new();
+        while let Some(start) = pem.find(PEM_BEGIN) {
+            pem = &pem[start + PEM_BEGIN.len()..];
+            let end = pem
+                .find(PEM_END)
+                .ok_or_else(|| invalid("unterminated PEM certificate"))?;
+            let der = base64::decode(&pem[..end]).ok_or_else(|| invalid("invalid PEM"))?;
+            certificates.push(Certificate::from_der(&der)?);
+            pem = &pem[end + PEM_END.len()..];
+        }
+        Ok(certificates)
+    }
+
+    #[cfg(feature = "rustls-tls")]
+    fn validate(&self) -> Result<(), Error> {
+        rustls::RootCertStore::empty()
+            .add(&rustls::Certificate(self.der.clone()))
+            .map_err(|err| invalid(&err.to_string()))
+    }
+
+    #[cfg(all(feature = "native-tls", not(feature = "rustls-tls")))]
+    fn validate(&self) -> Result<(), Error> {
+        native_tls_crate::Certificate::from_der(&self.der)
+            .map(|_| ())
+            .map_err(|err| invalid(&err.to_string()))
+    }
+}
+
+impl fmt::Debug for Certificate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Certificate")
+            .field("len", &self.der.len())
+            .finish()
+    }
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidCertificate {
+        reason: reason.to_string(),
+    }
+}
+
+impl TlsConnector {
+    /// Wraps `connector` to establish https connections, trusting the
+    /// root certificates in `config`.
+    pub(crate) fn new(connector: Connector, config: &Config) -> TlsConnector {
+        TlsConnector {
+            https: https_connector(connector, config),
+        }
+    }
+}
+
+impl Service<Uri> for TlsConnector {
+    type Response = TlsStream;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<TlsStream, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        self.https.poll_ready(cx)
+    }
+
+    fn call(&mut self, url: Uri) -> Self::Future {
+        let span = trace_span!("connect", url = %url);
+        let connecting = self.https.call(url);
+        Box::pin(
+            async move {
+                let inner = match connecting.await {
+                    Ok(inner) => inner,
+                    Err(err) => {
+                        trace_event!(debug, error = %err, "connection failed");
+                        return Err(err);
+                    }
+                };
+                let peer_certificates = peer_certificates(&inner).map(|chain| {
+                    let chain = chain
+                        .iter()
+                        .filter_map(|der| PeerCertificate::from_der(der))
+                        .collect();
+                    PeerCertificates(chain)
+                });
+                trace_event!(debug, tls = peer_certificates.is_some(), "connected");
+                Ok(TlsStream {
+                    inner,
+                    peer_certificates,
+                })
+            }
+            .instrument(span),
+        )
+    }
+}
+
+impl Connection for TlsStream {
+    fn connected(&self) -> Connected {
+        let connected = self.inner.connected();
+        match &self.peer_certificates {
+            Some(peer_certificates) => connected.extra(peer_certificates.clone()),
+            None => connected,
+        }
+    }
+}
+
+impl AsyncRead for TlsStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for TlsStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Returns the DER encoded certificate chain sent by the server, or
+/// `None` if the connection is not encrypted.
+#[cfg(feature = "rustls-tls")]
+fn peer_certificates(stream: &HttpsStream) -> Option<Vec<Vec<u8>>> {
+    match stream {
+        hyper_rustls::MaybeHttpsStream::Http(_) => None,
+        hyper_rustls::MaybeHttpsStream::Https(tls) => {
+            let chain = tls.get_ref().1.peer_certificates().unwrap_or_default();
+            Some(chain.iter().map(|der| der.0.clone()).collect())
+        }
+    }
+}
+
+/// Returns the DER encoded certificate chain sent by the server, or
+/// `None` if the connection is not encrypted.
+///
+/// `native-tls` only exposes the leaf certificate.
+#[cfg(all(feature = "native-tls", not(feature = "rustls-tls")))]
+fn peer_certificates(stream: &HttpsStream) -> Option<Vec<Vec<u8>>> {
+    match stream {
+        hyper_tls::MaybeHttpsStream::Http(_) => None,
+        hyper_tls::MaybeHttpsStream::Https(tls) => {
+            let leaf = tls.get_ref().peer_certificate().ok().flatten();
+            Some(
+                leaf.and_then(|leaf| leaf.to_der().ok())
+                    .into_iter()
+                    .collect(),
+            )
+        }
+    }
+}
+
+#[cfg(feature = "rustls-tls")]
+fn https_connector(connector: Connector, config: &Config) -> HttpsConnector {
+    let mut roots = rustls::RootCertStore::empty();
+    if config.built_in_root_certs {
+        roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+            rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+                anchor.subject,
+                anchor.spki,
+                anchor.name_constraints,
+            )
+        }));
+    }
+    for certificate in &config.root_certificates {
+        roots
+            .add(&rustls::Certificate(certificate.der.clone()))
+            .expect("certificates are validated when created");
+    }
+    let tls = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    hyper_rustls::HttpsConnectorBuilder::new()
+        .with_tls_config(tls)
+        .https_or_http()
+        .enable_http1()
+        .wrap_connector(connector)
+}
+
+#[cfg(all(feature = "native-tls", not(feature = "rustls-tls")))]
+fn https_connector(connector: Connector, config: &Config) -> HttpsConnector {
+    let mut tls = native_tls_crate::TlsConnector::builder();
+    tls.disable_built_in_roots(!config.built_in_root_certs);
+    for certificate in &config.root_certificates {
+        let certificate = native_tls_crate::Certificate::from_der(&certificate.der)
+            .expect("certificates are validated when created");
+        tls.add_root_certificate(certificate);
+    }
+    let tls = tls.build().expect("native-tls failed to initialize");
+    hyper_tls::HttpsConnector::from((connector, tokio_native_tls::TlsConnector::from(tls)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::testing::{respond, stellar_toml_response};
+    use crate::{Fingerprint, StellarTomlClient};
+    use std::sync::Arc;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+    use tokio_rustls::rustls;
+
+    struct TestCa {
+        ca_pem: String,
+        server_der: Vec<u8>,
+        key_der: Vec<u8>,
+        public_key_der: Vec<u8>,
+    }
+
+    fn test_ca() -> TestCa {
+        let mut params = rcgen::CertificateParams::new(Vec::new());
+        params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, "stellar-toml test CA");
+        let ca = rcgen::Certificate::from_params(params).unwrap();
+        let names = vec!["localhost".to_string(), "stellar.example.org".to_string()];
+        let server = rcgen::generate_simple_self_signed(names).unwrap();
+        TestCa {
+            ca_pem: ca.serialize_pem().unwrap(),
+            server_der: server.serialize_der_with_signer(&ca).unwrap(),
+            key_der: server.serialize_private_key_der(),
+            public_key_der: server.get_key_pair().public_key_der(),
+        }
+    }
+
+    /// Serves `stellar.toml` over https to every connection.
+    async fn https_server(ca: &TestCa) -> u16 {
+        let config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![rustls::Certificate(ca.server_der.clone())],
+                rustls::PrivateKey(ca.key_der.clone()),
+            )
+            .unwrap();
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    let mut stream = match acceptor.accept(stream).await {
+                        Ok(stream) => stream,
+                        Err(_) => return,
+                    };
+                    respond(&mut stream, &stellar_toml_response()).await;
+                    stream.shutdown().await.unwrap();
+                });
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn test_certificate_from_pem() {
+        let ca = test_ca();
+        let bundle = format!("{}\n{}", ca.ca_pem, ca.ca_pem);
+        assert_eq!(
+            2,
+            Certificate::from_pem_bundle(bundle.as_bytes())
+                .unwrap()
+                .len()
+        );
+        assert!(Certificate::from_pem(ca.ca_pem.as_bytes()).is_ok());
+        assert!(matches!(
+            Certificate::from_pem(b"not a certificate"),
+            Err(Error::InvalidCertificate { .. })
+        ));
+        assert!(Certificate::from_der(b"not a certificate").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_with_custom_root_certificate() {
+        let ca = test_ca();
+        let port = https_server(&ca).await;
+        let url = format!("https://localhost:{}/.well-known/stellar.toml", port)
+            .parse()
+            .unwrap();
+
+        let client = StellarTomlClient::builder()
+            .allow_ip_range("127.0.0.0/8".parse().unwrap())
+            .add_root_certificate(Certificate::from_pem(ca.ca_pem.as_bytes()).unwrap())
+            .tls_built_in_root_certs(false)
+            .build();
+        let stellar = client.resolve_url(&url).await.unwrap();
+        assert_eq!(Some("2.0.0".to_string()), stellar.version);
+
+        let client = StellarTomlClient::builder()
+            .allow_ip_range("127.0.0.0/8".parse().unwrap())
+            .build();
+        let err = client.resolve_url(&url).await.unwrap_err();
+        assert!(!err.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_with_host_override() {
+        let ca = test_ca();
+        let port = https_server(&ca).await;
+        let client = StellarTomlClient::builder()
+            .add_root_certificate(Certificate::from_pem(ca.ca_pem.as_bytes()).unwrap())
+            .override_host("Stellar.Example.org", ([127, 0, 0, 1], port).into())
+            .build();
+        let stellar = client.resolve("stellar.example.org").await.unwrap();
+        assert_eq!(Some("2.0.0".to_string()), stellar.version);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_reports_peer_certificates() {
+        let ca = test_ca();
+        let port = https_server(&ca).await;
+        let url = format!("https://localhost:{}/.well-known/stellar.toml", port)
+            .parse()
+            .unwrap();
+        let public_key = Fingerprint::of(&ca.public_key_der);
+        let builder = StellarTomlClient::builder()
+            .allow_ip_range("127.0.0.0/8".parse().unwrap())
+            .add_root_certificate(Certificate::from_pem(ca.ca_pem.as_bytes()).unwrap());
+
+        let client = builder
+            .clone()
+            .pin_public_key("localhost", public_key)
+            .build();
+        let fetched = client.resolve_url_with_metadata(&url).await.unwrap();
+        assert_eq!(
+            PeerCertificate::from_der(&ca.server_der),
+            fetched.peer_certificates.first().cloned()
+        );
+        assert_eq!(public_key, fetched.peer_certificates[0].public_key);
+
+        let client = builder
+            .pin_public_key("localhost", Fingerprint::of(b"other key"))
+            .build();
+        let result = client.resolve_url(&url).await;
+        assert!(matches!(result, Err(Error::CertificatePinMismatch { .. })));
+    }
+}
@@ -0,0 +1,142 @@
+//! Instrumentation with `tracing`, compiled out without the `tracing`
+//! feature.
+//!
+//! The macros take the same arguments as the `tracing` macros, and
+//! expand to nothing when the feature is disabled, so that call sites
+//! don't need to be gated.
+
+/// Creates a `debug` level span.
+#[cfg(feature = "tracing")]
+macro_rules! trace_span {
+    ($($args:tt)*) => {
+        tracing::debug_span!($($args)*)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_span {
+    ($($args:tt)*) => {
+        crate::trace::Span
+    };
+}
+
+/// Records an event at the given level, one of the `tracing` macros.
+#[cfg(feature = "tracing")]
+macro_rules! trace_event {
+    ($level:ident, $($args:tt)*) => {
+        tracing::$level!($($args)*)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_event {
+    ($level:ident, $($args:tt)*) => {};
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::Instrument;
+
+/// Span used when the `tracing` feature is disabled.
+#[cfg(not(feature = "tracing"))]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Span;
+
+#[cfg(not(feature = "tracing"))]
+impl Span {
+    pub(crate) fn entered(self) -> Span {
+        self
+    }
+}
+
+/// Attaches a span to a future, when the `tracing` feature is enabled.
+#[cfg(not(feature = "tracing"))]
+pub(crate) trait Instrument: Sized {
+    fn instrument(self, _span: Span) -> Self {
+        self
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+impl<T> Instrument for T {}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use crate::transport::{MemoryResponse, MemoryTransport};
+    use crate::StellarTomlClient;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    /// Records the names of the spans and the messages and fields of
+    /// the events.
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    struct Fields<'a>(&'a mut String);
+
+    impl Visit for Fields<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0.push_str(&format!(" {}={:?}", field.name(), value));
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut line = format!("span {}", span.metadata().name());
+            span.record(&mut Fields(&mut line));
+            let mut lines = self.0.lock().unwrap();
+            lines.push(line);
+            Id::from_u64(lines.len() as u64)
+        }
+
+        fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let mut line = "event".to_string();
+            event.record(&mut Fields(&mut line));
+            self.0.lock().unwrap().push(line);
+        }
+
+        fn enter(&self, _span: &Id) {}
+
+        fn exit(&self, _span: &Id) {}
+    }
+
+    #[test]
+    fn test_resolve_records_spans_and_events() {
+        let url = "https://example.org/.well-known/stellar.toml";
+        let transport = MemoryTransport::new()
+            .with_response(url, MemoryResponse::ok("VERSION = \"2.0.0\""))
+            .with_response(
+                "https://invalid.example.org/.well-known/stellar.toml",
+                MemoryResponse::ok("VERSION = "),
+            );
+        let client = StellarTomlClient::with_transport(transport);
+        let recorder = Recorder::default();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        tracing::subscriber::with_default(recorder.clone(), || {
+            runtime.block_on(async {
+                client.resolve("example.org").await.unwrap();
+                client.resolve("invalid.example.org").await.unwrap_err();
+            })
+        });
+
+        let lines = recorder.0.lock().unwrap();
+        let has = |expected: &str| lines.iter().any(|line| line.contains(expected));
+        assert!(has("span resolve domain=example.org"), "{:#?}", lines);
+        assert!(has("span request url=https://example.org/"), "{:#?}", lines);
+        assert!(has("span parse bytes=17"), "{:#?}", lines);
+        assert!(has("status=200"), "{:#?}", lines);
+        assert!(has("error.kind=\"toml\""), "{:#?}", lines);
+    }
+}
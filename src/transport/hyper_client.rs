@@ -0,0 +1,305 @@
+use super::resolver::{filter_addresses, Resolver};
+use super::{Body, ResponseFuture, Transport};
+use crate::client::Config;
+use crate::ip_filter::{BlockedAddress, IpFilter};
+use crate::proxy::{BoxError, Proxy, ProxyError};
+use crate::tls::TlsConnector;
+use crate::trace::Instrument;
+use crate::{Error, TimeoutPhase};
+use futures_util::future::TryFutureExt;
+use futures_util::stream::TryStreamExt;
+use http::uri::Uri;
+use http::Request;
+use hyper::client::connect::dns::Name;
+use hyper::client::HttpConnector;
+use hyper::service::Service;
+use hyper::Client;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::net::TcpStream;
+
+/// A transport backed by a pooled `hyper` client.
+///
+/// Cloning the transport is cheap and all clones share the same
+/// connection pool.
+#[derive(Debug, Clone)]
+pub struct HyperTransport {
+    client: Client<TlsConnector>,
+}
+
+/// Connector that opens tcp connections, optionally tunneled through
+/// a proxy.
+///
+/// Direct connections are established with the overridden address of
+/// the host if any, or else with the resolved addresses allowed by the
+/// ip filter. Connections tunneled through a proxy are filtered if the
+/// host is an ip address, or if the proxy is sent the address resolved
+/// by the client.
+#[derive(Debug, Clone)]
+pub(crate) struct Connector {
+    http: HttpConnector<Resolver>,
+    resolver: Resolver,
+    unfiltered_http: HttpConnector,
+    proxy: Option<Arc<Proxy>>,
+    filter: Arc<IpFilter>,
+    host_overrides: Arc<HashMap<String, SocketAddr>>,
+    connect_timeout: Option<Duration>,
+}
+
+impl HyperTransport {
+    /// Creates a new transport with the default configuration.
+    pub fn new() -> HyperTransport {
+        HyperTransport::from_config(&Config::default())
+    }
+
+    pub(crate) fn from_config(config: &Config) -> HyperTransport {
+        let filter = Arc::new(config.ip_filter.clone());
+        let resolver = Resolver::new(config.dns_resolver.clone(), filter.clone());
+        let mut http = HttpConnector::new_with_resolver(resolver.clone());
+        http.enforce_http(false);
+        http.set_connect_timeout(config.connect_timeout);
+        let mut unfiltered_http = HttpConnector::new();
+        unfiltered_http.enforce_http(false);
+        unfiltered_http.set_connect_timeout(config.connect_timeout);
+        let connector = Connector {
+            http,
+            resolver,
+            unfiltered_http,
+            proxy: config.proxy.clone().map(Arc::new),
+            filter,
+            host_overrides: Arc::new(config.host_overrides.clone()),
+            connect_timeout: config.connect_timeout,
+        };
+        let https = TlsConnector::new(connector, config);
+        let client = Client::builder().build::<_, hyper::Body>(https);
+        HyperTransport { client }
+    }
+}
+
+impl Default for HyperTransport {
+    fn default() -> HyperTransport {
+        HyperTransport::new()
+    }
+}
+
+impl Transport for HyperTransport {
+    fn send(&self, request: Request<()>) -> ResponseFuture {
+        let client = self.client.clone();
+        Box::pin(async move {
+            let url = request.uri().clone();
+            let request = request.map(|_| hyper::Body::empty());
+            let response = match client.request(request).await {
+                Ok(response) => response,
+                Err(err) => return Err(connect_error(err, url)),
+            };
+            Ok(response.map(|body| {
+                Body::wrap_stream(body.map_err(move |err| hyper_error(err, url.clone())))
+            }))
+        })
+    }
+}
+
+impl Service<Uri> for Connector {
+    type Response = TcpStream;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<TcpStream, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        futures_util::ready!(self.http.poll_ready(cx))?;
+        self.unfiltered_http.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, url: Uri) -> Self::Future {
+        let span = trace_span!("tcp", host = %url.host().unwrap_or_default());
+        let connecting = self.connect(url);
+        Box::pin(
+            async move {
+                let stream = connecting.await?;
+                trace_event!(debug, address = ?stream.peer_addr().ok(), "tcp connected");
+                Ok(stream)
+            }
+            .instrument(span),
+        )
+    }
+}
+
+impl Connector {
+    fn connect(&mut self, url: Uri) -> <Self as Service<Uri>>::Future {
+        let proxy = match &self.proxy {
+            Some(proxy) if proxy.intercepts(&url) => proxy.clone(),
+            _ => return self.connect_direct(url),
+        };
+        if let Err(err) = self.filter_ip_host(&url) {
+            return Box::pin(async move { Err(err) });
+        }
+        let resolving = if proxy.resolves_locally() {
+            Some(self.resolve(&url))
+        } else {
+            None
+        };
+        let connecting = self.unfiltered_http.call(proxy.uri().clone());
+        let tunneling = async move {
+            let address = match resolving {
+                Some(resolving) => Some(resolving.await?),
+                None => None,
+            };
+            let mut stream = connecting.await?;
+            proxy.handshake(&mut stream, &url, address).await?;
+            Ok(stream)
+        };
+        // The connect timeout covers both the connection to the proxy
+        // and the handshake.
+        match self.connect_timeout {
+            None => Box::pin(tunneling),
+            Some(timeout) => Box::pin(async move {
+                match tokio::time::timeout(timeout, tunneling).await {
+                    Ok(result) => result,
+                    Err(_) => Err(io::Error::from(io::ErrorKind::TimedOut).into()),
+                }
+            }),
+        }
+    }
+
+    /// Resolves the host of `url` to an address allowed by the ip
+    /// filter.
+    fn resolve(
+        &mut self,
+        url: &Uri,
+    ) -> Pin<Box<dyn Future<Output = Result<IpAddr, BoxError>> + Send>> {
+        let host = url.host().unwrap_or_default();
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Box::pin(async move { Ok(ip) });
+        }
+        let resolving = match host.parse::<Name>() {
+            Ok(name) => self.resolver.call(name),
+            Err(err) => return Box::pin(async move { Err(err.into()) }),
+        };
+        Box::pin(async move {
+            let mut addresses = resolving.await?;
+            match addresses.next() {
+                Some(address) => Ok(address.ip()),
+                None => Err(io::Error::from(io::ErrorKind::NotFound).into()),
+            }
+        })
+    }
+
+    fn connect_direct(&mut self, url: Uri) -> <Self as Service<Uri>>::Future {
+        let host = url.host().unwrap_or_default();
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if let Some(address) = self.host_overrides.get(&host.to_ascii_lowercase()) {
+            // Connect to the address instead of the host, the tls
+            // connector still uses the host for SNI.
+            let target = Uri::builder()
+                .scheme("http")
+                .authority(address.to_string().as_str())
+                .path_and_query("/")
+                .build();
+            return match target {
+                Ok(target) => Box::pin(self.unfiltered_http.call(target).map_err(Into::into)),
+                Err(err) => Box::pin(async move { Err(err.into()) }),
+            };
+        }
+        if let Err(err) = self.filter_ip_host(&url) {
+            return Box::pin(async move { Err(err) });
+        }
+        Box::pin(self.http.call(url).map_err(Into::into))
+    }
+
+    /// Checks the host of `url` against the ip filter if it's an ip
+    /// address, since the resolver is skipped for ip addresses.
+    fn filter_ip_host(&self, url: &Uri) -> Result<(), BoxError> {
+        let host = url.host().unwrap_or_default();
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        match host.parse::<IpAddr>() {
+            Ok(ip) => filter_addresses(&self.filter, vec![SocketAddr::new(ip, 0)]).map(|_| ()),
+            Err(_) => Ok(()),
+        }
+    }
+}
+
+/// Converts an error returned by `hyper` while sending a request
+/// to `url`.
+fn connect_error(err: hyper::Error, url: Uri) -> Error {
+    if is_connect_timeout(&err) {
+        return Error::Timeout {
+            phase: TimeoutPhase::Connect,
+            url,
+        };
+    }
+    let mut source = std::error::Error::source(&err);
+    while let Some(inner) = source {
+        if let Some(proxy_err) = inner.downcast_ref::<ProxyError>() {
+            return Error::Proxy {
+                reason: proxy_err.to_string(),
+                url,
+            };
+        }
+        if let Some(BlockedAddress(address)) = inner.downcast_ref::<BlockedAddress>() {
+            return Error::BlockedAddress {
+                address: *address,
+                url,
+            };
+        }
+        source = inner.source();
+    }
+    hyper_error(err, url)
+}
+
+/// Converts an error returned by `hyper` while requesting `url`,
+/// keeping it as the source so that it can be inspected when deciding
+/// to retry.
+fn hyper_error(err: hyper::Error, url: Uri) -> Error {
+    Error::TransportError {
+        url,
+        source: Arc::new(err),
+    }
+}
+
+fn is_connect_timeout(err: &hyper::Error) -> bool {
+    if !err.is_connect() {
+        return false;
+    }
+    let mut source = std::error::Error::source(err);
+    while let Some(err) = source {
+        if let Some(io_err) = err.downcast_ref::<io::Error>() {
+            if io_err.kind() == io::ErrorKind::TimedOut {
+                return true;
+            }
+        }
+        source = err.source();
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Error, StellarTomlClient};
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_resolve_connection_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        drop(listener);
+        let url = format!("http://{}/.well-known/stellar.toml", address)
+            .parse()
+            .unwrap();
+        let client = StellarTomlClient::builder()
+            .allow_ip_range("127.0.0.0/8".parse().unwrap())
+            .build();
+        let err = client.resolve_url(&url).await.unwrap_err();
+        assert!(matches!(err, Error::TransportError { .. }));
+        assert_eq!(Some(&url), err.url());
+        assert!(err.is_retryable());
+        let message = err.to_string();
+        assert!(message.starts_with(&format!("transport error requesting {}: ", url)));
+        assert!(message.contains("connect"), "{}", message);
+    }
+}
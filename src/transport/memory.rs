@@ -0,0 +1,149 @@
+use super::{Body, ResponseFuture, Transport};
+use crate::pinning::{PeerCertificate, PeerCertificates};
+use bytes::Bytes;
+use http::header::{HeaderName, HeaderValue};
+use http::{HeaderMap, Request, Response, StatusCode};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+/// A transport that serves canned responses from memory.
+///
+/// Responses are keyed by the full request url. Requests to urls
+/// without a response return `404 Not Found`. The transport records
+/// all requests it receives, so that tests can inspect them.
+///
+/// ```rust
+/// use stellar_toml::{MemoryResponse, MemoryTransport, StellarTomlClient};
+///
+/// # async fn run() -> std::result::Result<(), stellar_toml::Error> {
+/// let transport = MemoryTransport::new().with_response(
+///     "https://example.org/.well-known/stellar.toml",
+///     MemoryResponse::ok("VERSION = \"2.0.0\""),
+/// );
+/// let client = StellarTomlClient::with_transport(transport);
+/// let stellar = client.resolve("example.org").await?;
+/// assert_eq!(Some("2.0.0".to_string()), stellar.version);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MemoryTransport {
+    responses: Arc<Mutex<HashMap<String, VecDeque<MemoryResponse>>>>,
+    requests: Arc<Mutex<Vec<Request<()>>>>,
+}
+
+/// A canned response served by `MemoryTransport`.
+#[derive(Debug, Clone)]
+pub struct MemoryResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    peer_certificates: Option<Vec<PeerCertificate>>,
+}
+
+impl MemoryTransport {
+    /// Creates a new transport without any response.
+    pub fn new() -> MemoryTransport {
+        MemoryTransport::default()
+    }
+
+    /// Returns the transport, serving `response` for requests to `url`.
+    pub fn with_response(self, url: &str, response: MemoryResponse) -> MemoryTransport {
+        self.insert(url, response);
+        self
+    }
+
+    /// Serves `response` for requests to `url`, replacing any previous response.
+    pub fn insert(&self, url: &str, response: MemoryResponse) {
+        let mut responses = self.responses.lock().unwrap();
+        responses.insert(url.to_string(), VecDeque::from(vec![response]));
+    }
+
+    /// Queues `response` for requests to `url`.
+    ///
+    /// Queued responses are served once each, in order, and the last
+    /// one is served for all remaining requests.
+    pub fn push(&self, url: &str, response: MemoryResponse) {
+        let mut responses = self.responses.lock().unwrap();
+        responses
+            .entry(url.to_string())
+            .or_default()
+            .push_back(response);
+    }
+
+    /// Returns the requests received by the transport, in order.
+    pub fn requests(&self) -> Vec<Request<()>> {
+        let requests = self.requests.lock().unwrap();
+        requests
+            .iter()
+            .map(|request| {
+                let mut copy = Request::new(());
+                *copy.method_mut() = request.method().clone();
+                *copy.uri_mut() = request.uri().clone();
+                *copy.headers_mut() = request.headers().clone();
+                copy
+            })
+            .collect()
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn send(&self, request: Request<()>) -> ResponseFuture {
+        let response = {
+            let mut responses = self.responses.lock().unwrap();
+            match responses.get_mut(&request.uri().to_string()) {
+                Some(queue) if queue.len() > 1 => queue.pop_front(),
+                Some(queue) => queue.front().cloned(),
+                None => None,
+            }
+        };
+        let response =
+            response.unwrap_or_else(|| MemoryResponse::new(StatusCode::NOT_FOUND, Bytes::new()));
+        self.requests.lock().unwrap().push(request);
+        Box::pin(async move { Ok(response.into_response()) })
+    }
+}
+
+impl MemoryResponse {
+    /// Creates a new response with the given `status` and `body`.
+    pub fn new<B: Into<Bytes>>(status: StatusCode, body: B) -> MemoryResponse {
+        MemoryResponse {
+            status,
+            headers: HeaderMap::new(),
+            body: body.into(),
+            peer_certificates: None,
+        }
+    }
+
+    /// Creates a new `200 OK` response with the given `body`.
+    pub fn ok<B: Into<Bytes>>(body: B) -> MemoryResponse {
+        MemoryResponse::new(StatusCode::OK, body)
+    }
+
+    /// Returns the response with the header `name` set to `value`.
+    ///
+    /// Panics if `name` or `value` are not valid header names or values.
+    pub fn with_header(mut self, name: &str, value: &str) -> MemoryResponse {
+        let name = HeaderName::from_bytes(name.as_bytes()).expect("invalid header name");
+        let value = HeaderValue::from_str(value).expect("invalid header value");
+        self.headers.append(name, value);
+        self
+    }
+
+    /// Returns the response, as if served over a tls connection with
+    /// the given certificate chain.
+    pub fn with_peer_certificates(mut self, chain: Vec<PeerCertificate>) -> MemoryResponse {
+        self.peer_certificates = Some(chain);
+        self
+    }
+
+    fn into_response(self) -> Response<Body> {
+        let mut response = Response::new(Body::from(self.body));
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers;
+        if let Some(chain) = self.peer_certificates {
+            response.extensions_mut().insert(PeerCertificates(chain));
+        }
+        response
+    }
+}
@@ -0,0 +1,156 @@
+//! HTTP transports used to fetch `stellar.toml` files.
+//!
+//! The resolver is generic over the `Transport` trait, so that users
+//! can plug their own HTTP stack. The crate provides a
+//! `HyperTransport`, used by default, and a `MemoryTransport` that
+//! serves canned responses and is useful for testing.
+use crate::Error;
+use bytes::Bytes;
+use futures_core::Stream;
+use futures_util::stream::{self, StreamExt};
+use http::{Request, Response};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+#[cfg(feature = "hyper-transport")]
+mod hyper_client;
+mod memory;
+#[cfg(feature = "hyper-transport")]
+mod resolver;
+
+#[cfg(feature = "hyper-transport")]
+pub(crate) use hyper_client::Connector;
+#[cfg(feature = "hyper-transport")]
+pub use hyper_client::HyperTransport;
+pub use memory::{MemoryResponse, MemoryTransport};
+#[cfg(feature = "hyper-transport")]
+pub(crate) use resolver::SharedDnsResolver;
+#[cfg(feature = "hyper-transport")]
+pub use resolver::{DnsResolver, LookupFuture};
+
+/// Future returned by `Transport::send`.
+pub type ResponseFuture = Pin<Box<dyn Future<Output = Result<Response<Body>, Error>> + Send>>;
+
+/// A transport sends http requests and returns their response.
+///
+/// The returned response should contain the status code, headers
+/// and the (unread) body of the response.
+pub trait Transport: Send + Sync + 'static {
+    /// Sends `request` and returns its response.
+    fn send(&self, request: Request<()>) -> ResponseFuture;
+}
+
+/// A streaming response body.
+pub struct Body {
+    inner: Pin<Box<dyn Stream<Item = Result<Bytes, Error>> + Send>>,
+}
+
+impl Body {
+    /// Creates an empty body.
+    pub fn empty() -> Body {
+        Body::wrap_stream(stream::empty())
+    }
+
+    /// Creates a body from a stream of chunks.
+    pub fn wrap_stream<S>(stream: S) -> Body
+    where
+        S: Stream<Item = Result<Bytes, Error>> + Send + 'static,
+    {
+        Body {
+            inner: Box::pin(stream),
+        }
+    }
+
+    /// Returns the next chunk of the body, or `None` if the body was
+    /// read completely.
+    pub async fn chunk(&mut self) -> Option<Result<Bytes, Error>> {
+        self.inner.next().await
+    }
+}
+
+impl Stream for Body {
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.as_mut().poll_next(cx)
+    }
+}
+
+impl From<Bytes> for Body {
+    fn from(bytes: Bytes) -> Body {
+        Body::wrap_stream(stream::once(async move { Ok(bytes) }))
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Body {
+        Body::from(Bytes::from(bytes))
+    }
+}
+
+impl From<String> for Body {
+    fn from(s: String) -> Body {
+        Body::from(Bytes::from(s))
+    }
+}
+
+impl From<&'static str> for Body {
+    fn from(s: &'static str) -> Body {
+        Body::from(Bytes::from_static(s.as_bytes()))
+    }
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Body").finish()
+    }
+}
+
+/// Helpers to serve canned http responses in tests.
+#[cfg(all(test, feature = "hyper-transport"))]
+pub(crate) mod testing {
+    use std::net::SocketAddr;
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// The `stellar.toml` file served by `stellar_toml_response`.
+    pub(crate) const STELLAR_TOML: &str = "VERSION = \"2.0.0\"\n";
+
+    /// Returns a `200 OK` response serving `STELLAR_TOML`.
+    pub(crate) fn stellar_toml_response() -> String {
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            STELLAR_TOML.len(),
+            STELLAR_TOML
+        )
+    }
+
+    /// Reads the head of a request from `stream` and writes
+    /// `response`, returning the request head.
+    pub(crate) async fn respond<S>(stream: &mut S, response: &str) -> String
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut request = Vec::new();
+        while !request.ends_with(b"\r\n\r\n") {
+            request.push(stream.read_u8().await.unwrap());
+        }
+        stream.write_all(response.as_bytes()).await.unwrap();
+        String::from_utf8(request).unwrap()
+    }
+
+    /// Serves `STELLAR_TOML` to every connection on a loopback address.
+    pub(crate) async fn serve_stellar_toml() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                respond(&mut stream, &stellar_toml_response()).await;
+            }
+        });
+        address
+    }
+}
@@ -0,0 +1,205 @@
+use crate::ip_filter::{BlockedAddress, IpFilter};
+use crate::proxy::BoxError;
+use crate::trace::Instrument;
+use hyper::client::connect::dns::{GaiResolver, Name};
+use hyper::service::Service;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+/// Future returned by `DnsResolver::lookup`.
+pub type LookupFuture = Pin<Box<dyn Future<Output = io::Result<Vec<IpAddr>>> + Send>>;
+
+/// A DNS resolver returns the addresses of a host.
+///
+/// The `HyperTransport` resolves hosts with the system resolver,
+/// unless a resolver is set with `ClientBuilder::dns_resolver`.
+///
+/// ```rust
+/// use stellar_toml::transport::{DnsResolver, LookupFuture};
+/// use std::net::{IpAddr, Ipv4Addr};
+///
+/// /// Resolves every host to the same address.
+/// struct StaticResolver(IpAddr);
+///
+/// impl DnsResolver for StaticResolver {
+///     fn lookup(&self, _host: &str) -> LookupFuture {
+///         let address = self.0;
+///         Box::pin(async move { Ok(vec![address]) })
+///     }
+/// }
+///
+/// let resolver = StaticResolver(IpAddr::V4(Ipv4Addr::new(203, 0, 113, 1)));
+/// let client = stellar_toml::StellarTomlClient::builder()
+///     .dns_resolver(resolver)
+///     .build();
+/// ```
+pub trait DnsResolver: Send + Sync + 'static {
+    /// Returns the addresses of `host`.
+    fn lookup(&self, host: &str) -> LookupFuture;
+}
+
+/// A `DnsResolver` shared between clients.
+#[derive(Clone)]
+pub(crate) struct SharedDnsResolver(pub(crate) Arc<dyn DnsResolver>);
+
+impl fmt::Debug for SharedDnsResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DnsResolver")
+    }
+}
+
+/// DNS resolver used by `HyperTransport`, that drops the addresses
+/// rejected by the ip filter.
+///
+/// Filtering the resolved addresses, rather than checking the host
+/// before connecting, ensures that the checked addresses are the
+/// ones the connection is established with.
+#[derive(Debug, Clone)]
+pub(crate) struct Resolver {
+    gai: GaiResolver,
+    custom: Option<SharedDnsResolver>,
+    filter: Arc<IpFilter>,
+}
+
+impl Resolver {
+    pub(crate) fn new(custom: Option<SharedDnsResolver>, filter: Arc<IpFilter>) -> Resolver {
+        Resolver {
+            gai: GaiResolver::new(),
+            custom,
+            filter,
+        }
+    }
+}
+
+impl Service<Name> for Resolver {
+    type Response = std::vec::IntoIter<SocketAddr>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        self.gai.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let filter = self.filter.clone();
+        let span = trace_span!("dns", host = %name);
+        let resolving: Pin<Box<dyn Future<Output = io::Result<Vec<SocketAddr>>> + Send>> =
+            match &self.custom {
+                Some(SharedDnsResolver(custom)) => {
+                    let resolving = custom.lookup(name.as_str());
+                    Box::pin(async move {
+                        let addresses = resolving.await?;
+                        Ok(addresses
+                            .into_iter()
+                            .map(|ip| SocketAddr::new(ip, 0))
+                            .collect())
+                    })
+                }
+                None => {
+                    let resolving = self.gai.call(name);
+                    Box::pin(async move { Ok(resolving.await?.collect()) })
+                }
+            };
+        Box::pin(
+            async move {
+                let addresses = match resolving.await {
+                    Ok(addresses) => addresses,
+                    Err(err) => {
+                        trace_event!(debug, error = %err, "lookup failed");
+                        return Err(err.into());
+                    }
+                };
+                trace_event!(debug, addresses = addresses.len(), "resolved");
+                filter_addresses(&filter, addresses).map(Vec::into_iter)
+            }
+            .instrument(span),
+        )
+    }
+}
+
+/// Returns the `addresses` allowed by `filter`, or an error if none is.
+pub(crate) fn filter_addresses(
+    filter: &IpFilter,
+    addresses: Vec<SocketAddr>,
+) -> Result<Vec<SocketAddr>, BoxError> {
+    let blocked = addresses.first().map(SocketAddr::ip);
+    let allowed: Vec<SocketAddr> = addresses
+        .into_iter()
+        .filter(|address| filter.is_allowed(address.ip()))
+        .collect();
+    match blocked {
+        Some(blocked) if allowed.is_empty() => {
+            trace_event!(debug, address = %blocked, "address blocked");
+            Err(BlockedAddress(blocked).into())
+        }
+        _ => Ok(allowed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::testing::{respond, stellar_toml_response};
+    use crate::{Error, StellarTomlClient};
+    use tokio::net::TcpListener;
+
+    struct StaticResolver(IpAddr);
+
+    impl DnsResolver for StaticResolver {
+        fn lookup(&self, host: &str) -> LookupFuture {
+            let address = self.0;
+            let found = host == "stellar.example.org";
+            Box::pin(async move {
+                if found {
+                    Ok(vec![address])
+                } else {
+                    Err(io::Error::from(io::ErrorKind::NotFound))
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_with_dns_resolver() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            respond(&mut stream, &stellar_toml_response()).await
+        });
+        let url = format!(
+            "http://stellar.example.org:{}/.well-known/stellar.toml",
+            port
+        )
+        .parse()
+        .unwrap();
+        let loopback = IpAddr::from([127, 0, 0, 1]);
+
+        let client = StellarTomlClient::builder()
+            .dns_resolver(StaticResolver(loopback))
+            .build();
+        match client.resolve_url(&url).await {
+            Err(Error::BlockedAddress { address, .. }) => assert_eq!(loopback, address),
+            other => panic!("expected blocked address, got {:?}", other),
+        }
+
+        let client = StellarTomlClient::builder()
+            .dns_resolver(StaticResolver(loopback))
+            .allow_ip_range(loopback.into())
+            .build();
+        let stellar = client.resolve_url(&url).await.unwrap();
+        assert_eq!(Some("2.0.0".to_string()), stellar.version);
+        let request = server.await.unwrap();
+        let host = format!("host: stellar.example.org:{}\r\n", port);
+        assert!(request.to_ascii_lowercase().contains(&host));
+
+        let err = client.resolve("other.example.org").await.unwrap_err();
+        assert!(matches!(err, Error::TransportError { .. }));
+        assert!(!err.is_retryable());
+    }
+}